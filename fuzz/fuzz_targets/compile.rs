@@ -0,0 +1,18 @@
+#![no_main]
+
+use beefin::{compile, find_matching_bracket, Interpreter, InterpreterState};
+use libfuzzer_sys::fuzz_target;
+
+// Malformed programs must come back as errors, never as panics.
+fuzz_target!(|data: &[u8]| {
+    if let Some((&index, program)) = data.split_first() {
+        find_matching_bracket(program, index as usize);
+    }
+
+    let _ = compile(data);
+
+    let mut interpreter = InterpreterState::new();
+    interpreter.load_bytes(data);
+    let _ = interpreter.validate();
+    let _ = interpreter.prepare();
+});
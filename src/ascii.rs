@@ -0,0 +1,47 @@
+use std::io::{self, Write};
+
+/// Output adapter for plain-text terminals: bytes of 128 and above, which are
+/// not ASCII, are written as `placeholder` instead.
+pub struct AsciiWriter<W: Write> {
+    inner: W,
+    placeholder: u8,
+}
+
+impl<W: Write> AsciiWriter<W> {
+    /// Replaces non-ASCII bytes with `placeholder`, usually `b'?'`.
+    pub fn new(inner: W, placeholder: u8) -> Self {
+        AsciiWriter { inner, placeholder }
+    }
+}
+
+impl<W: Write> Write for AsciiWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let replaced: Vec<u8> = buf
+            .iter()
+            .map(|&byte| if byte.is_ascii() { byte } else { self.placeholder })
+            .collect();
+        self.inner.write_all(&replaced)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use crate::ascii::AsciiWriter;
+    use crate::CapturedOutput;
+
+    #[test]
+    fn ascii_writer_replaces_bytes_above_127() {
+        let buffer = CapturedOutput::default();
+        let mut writer = AsciiWriter::new(buffer.clone(), b'?');
+
+        writer.write_all(&[200, 65, 128, 127]).unwrap();
+
+        assert_eq!(*buffer.0.borrow(), b"?A?\x7f");
+    }
+}
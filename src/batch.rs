@@ -0,0 +1,18 @@
+use std::path::PathBuf;
+use rayon::prelude::*;
+use crate::{Interpreter, InterpreterError, InterpreterState, ProgramSource, RunResult};
+
+/// Runs every program file in `paths` in its own [`InterpreterState`] with
+/// default settings, spread over a thread pool, and returns their results in
+/// the order of `paths`. Each program's output is captured, and `,` reads
+/// EOF straight away.
+pub fn run_batch(paths: &[PathBuf]) -> Vec<Result<RunResult, InterpreterError>> {
+    paths
+        .par_iter()
+        .map(|path| {
+            let mut interpreter = InterpreterState::new();
+            interpreter.load(ProgramSource::File(path.clone()))?;
+            interpreter.run_captured(&[])
+        })
+        .collect()
+}
@@ -0,0 +1,112 @@
+use std::io::{self, Write};
+use crate::OutputBuffering;
+
+/// Output adapter that decides when `inner` is flushed: after every write,
+/// after every newline, or only when it is flushed itself, which the
+/// interpreter does before reading input and at the end of a run. Held-back
+/// bytes are written when the writer is dropped.
+pub struct BufferedOutput<W: Write> {
+    inner: W,
+    buffering: OutputBuffering,
+    pending: Vec<u8>,
+}
+
+impl<W: Write> BufferedOutput<W> {
+    pub fn new(inner: W, buffering: OutputBuffering) -> Self {
+        BufferedOutput { inner, buffering, pending: Vec::new() }
+    }
+
+    fn write_pending(&mut self, len: usize) -> io::Result<()> {
+        self.inner.write_all(&self.pending[..len])?;
+        self.pending.drain(..len);
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Write for BufferedOutput<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        match self.buffering {
+            OutputBuffering::None => self.write_pending(self.pending.len())?,
+            OutputBuffering::Line => {
+                if let Some(newline) = self.pending.iter().rposition(|&byte| byte == b'\n') {
+                    self.write_pending(newline + 1)?;
+                }
+            }
+            OutputBuffering::Full => {}
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_pending(self.pending.len())
+    }
+}
+
+impl<W: Write> Drop for BufferedOutput<W> {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io::{self, Write};
+    use crate::buffering::BufferedOutput;
+    use crate::{CapturedOutput, OutputBuffering};
+
+    /// Only passes writes on to `flushed` when flushed, like a buffered stdout.
+    pub(crate) struct FlushingWriter {
+        pending: Vec<u8>,
+        flushed: CapturedOutput,
+    }
+
+    impl FlushingWriter {
+        pub(crate) fn new(flushed: CapturedOutput) -> Self {
+            FlushingWriter { pending: Vec::new(), flushed }
+        }
+    }
+
+    impl Write for FlushingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.pending.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushed.0.borrow_mut().append(&mut self.pending);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn line_buffered_output_flushes_at_newlines() {
+        let flushed = CapturedOutput::default();
+        let sink = FlushingWriter::new(flushed.clone());
+        let mut writer = BufferedOutput::new(sink, OutputBuffering::Line);
+
+        writer.write_all(b"ab").unwrap();
+        assert_eq!(*flushed.0.borrow(), b"");
+        writer.write_all(b"c\nd").unwrap();
+        assert_eq!(*flushed.0.borrow(), b"abc\n");
+        writer.flush().unwrap();
+        assert_eq!(*flushed.0.borrow(), b"abc\nd");
+    }
+
+    #[test]
+    fn unbuffered_output_flushes_every_write_and_full_only_when_flushed() {
+        let flushed = CapturedOutput::default();
+        let sink = FlushingWriter::new(flushed.clone());
+        let mut writer = BufferedOutput::new(sink, OutputBuffering::None);
+        writer.write_all(b"a").unwrap();
+        assert_eq!(*flushed.0.borrow(), b"a");
+
+        let flushed = CapturedOutput::default();
+        let sink = FlushingWriter::new(flushed.clone());
+        let mut writer = BufferedOutput::new(sink, OutputBuffering::Full);
+        writer.write_all(b"a\nb").unwrap();
+        assert_eq!(*flushed.0.borrow(), b"");
+        drop(writer);
+        assert_eq!(*flushed.0.borrow(), b"a\nb");
+    }
+}
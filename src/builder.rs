@@ -0,0 +1,111 @@
+use crate::{
+    ArithmeticMode, CellWidth, Dialect, EofBehavior, Interpreter, InterpreterState, TapeEnd,
+    DEFAULT_TAPE_SIZE,
+};
+
+/// Chained configuration for an [`InterpreterState`], for when `new` and
+/// `with_config` don't cover enough. Unset options keep the defaults of
+/// `new`.
+///
+/// ```
+/// use beefin::{ArithmeticMode, CellWidth, InterpreterBuilder};
+///
+/// let interpreter = InterpreterBuilder::new()
+///     .tape_size(1000)
+///     .cell_width(CellWidth::Sixteen)
+///     .arithmetic(ArithmeticMode::Saturate)
+///     .build();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterpreterBuilder {
+    tape_size: usize,
+    cell_width: CellWidth,
+    eof: EofBehavior,
+    arithmetic: ArithmeticMode,
+    tape_end: TapeEnd,
+    max_steps: Option<u64>,
+    procedures: bool,
+}
+
+impl Default for InterpreterBuilder {
+    fn default() -> Self {
+        InterpreterBuilder {
+            tape_size: DEFAULT_TAPE_SIZE,
+            cell_width: CellWidth::Eight,
+            eof: EofBehavior::Zero,
+            arithmetic: ArithmeticMode::Wrap,
+            tape_end: TapeEnd::Wrap,
+            max_steps: None,
+            procedures: false,
+        }
+    }
+}
+
+impl InterpreterBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn tape_size(mut self, tape_size: usize) -> Self {
+        self.tape_size = tape_size;
+        self
+    }
+
+    pub fn cell_width(mut self, cell_width: CellWidth) -> Self {
+        self.cell_width = cell_width;
+        self
+    }
+
+    pub fn eof(mut self, eof: EofBehavior) -> Self {
+        self.eof = eof;
+        self
+    }
+
+    pub fn arithmetic(mut self, arithmetic: ArithmeticMode) -> Self {
+        self.arithmetic = arithmetic;
+        self
+    }
+
+    pub fn tape_end(mut self, tape_end: TapeEnd) -> Self {
+        self.tape_end = tape_end;
+        self
+    }
+
+    /// Sets the tape size, cell width, EOF, arithmetic, tape-end and
+    /// procedure options to those of `dialect`. Options set after this
+    /// override it.
+    pub fn dialect(self, dialect: Dialect) -> Self {
+        let eof = match dialect {
+            Dialect::Classic | Dialect::Pbrain => EofBehavior::Zero,
+            Dialect::Urban => EofBehavior::Unchanged,
+        };
+        self.tape_size(DEFAULT_TAPE_SIZE)
+            .cell_width(CellWidth::Eight)
+            .eof(eof)
+            .arithmetic(ArithmeticMode::Wrap)
+            .tape_end(TapeEnd::Wrap)
+            .procedures(dialect == Dialect::Pbrain)
+    }
+
+    /// Enables pbrain's procedure commands, see
+    /// [`Interpreter::set_procedures`].
+    pub fn procedures(mut self, enabled: bool) -> Self {
+        self.procedures = enabled;
+        self
+    }
+
+    pub fn max_steps(mut self, max_steps: u64) -> Self {
+        self.max_steps = Some(max_steps);
+        self
+    }
+
+    pub fn build(self) -> InterpreterState {
+        let mut interpreter = InterpreterState::with_config(self.tape_size, self.cell_width);
+        interpreter.set_eof_behavior(self.eof);
+        interpreter.set_arithmetic_mode(self.arithmetic);
+        interpreter.set_tape_end(self.tape_end);
+        interpreter.set_max_steps(self.max_steps);
+        interpreter.set_procedures(self.procedures);
+        interpreter
+    }
+}
@@ -0,0 +1,883 @@
+use std::str::FromStr;
+use log::{Level, log};
+use crate::{ArithmeticMode, InterpreterError, TapeEnd};
+
+/// A single Brainfuck command with comments stripped and loop targets resolved
+/// to indices into the compiled op list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Inc,
+    Dec,
+    Next,
+    Prev,
+    Print,
+    Input,
+    /// Net effect of a run of `+` and `-`.
+    Add(i32),
+    /// Net effect of a run of `>` and `<`.
+    Move(i32),
+    /// `>+<` and the like: adds the delta to the cell at the given offset
+    /// from the pointer, which ends up where it started.
+    AddAt(i32, i32),
+    /// A `[-]` or `[+]` loop, which always leaves the current cell at zero.
+    /// Holds what the body adds, -1 or 1, which decides how many passes the
+    /// loop would have taken.
+    SetZero(i32),
+    /// A `[>]`, `[<]` or `[>>…]` loop: moves the pointer by the given stride
+    /// until it reaches a zero cell.
+    SeekZero(i32),
+    /// `#`: dumps the cells around the pointer for debugging.
+    DebugDump,
+    /// `@`: copies the current cell into the register.
+    StoreRegister,
+    /// `$`: copies the register into the current cell.
+    LoadRegister,
+    /// A `:` outside a definition: defines the procedure numbered by the
+    /// current cell as the ops up to the matching `ProcedureEnd`, then jumps
+    /// past it.
+    ProcedureStart(usize),
+    /// The `:` that closes a definition: returns from the procedure being
+    /// called.
+    ProcedureEnd(usize),
+    /// `?`: calls the procedure numbered by the current cell.
+    Call,
+    /// `}`: switches to the next tape, wrapping around after the last.
+    NextTape,
+    /// `{`: switches to the previous tape, wrapping around before the first.
+    PreviousTape,
+    /// Jumps to the matching `LoopEnd` when the current cell is zero.
+    LoopStart(usize),
+    /// Jumps back to the matching `LoopStart` when the current cell is not zero.
+    LoopEnd(usize),
+}
+
+impl Op {
+    /// The source command this op executes, or starts with for ops that
+    /// replace several commands.
+    pub fn command(&self) -> char {
+        match self {
+            Op::Inc => '+',
+            Op::Dec => '-',
+            Op::Next => '>',
+            Op::Prev => '<',
+            Op::Print => '.',
+            Op::Input => ',',
+            Op::Add(delta) if *delta < 0 => '-',
+            Op::Add(_) => '+',
+            Op::Move(delta) if *delta < 0 => '<',
+            Op::Move(_) => '>',
+            Op::AddAt(offset, _) if *offset < 0 => '<',
+            Op::AddAt(..) => '>',
+            Op::SetZero(_) | Op::SeekZero(_) | Op::LoopStart(_) => '[',
+            Op::DebugDump => '#',
+            Op::StoreRegister => '@',
+            Op::LoadRegister => '$',
+            Op::ProcedureStart(_) | Op::ProcedureEnd(_) => ':',
+            Op::Call => '?',
+            Op::NextTape => '}',
+            Op::PreviousTape => '{',
+            Op::LoopEnd(_) => ']',
+        }
+    }
+}
+
+/// Which optimization passes run while compiling.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OptLevel {
+    /// One op per source command.
+    None,
+    /// Merge runs of `+`/`-` and `>`/`<`, and replace clear loops.
+    #[default]
+    Basic,
+    /// Also replace scan loops and drop runs without net effect.
+    Full,
+    /// Also remove loops that provably never run.
+    Aggressive,
+}
+
+impl FromStr for OptLevel {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "0" => Ok(OptLevel::None),
+            "1" => Ok(OptLevel::Basic),
+            "2" => Ok(OptLevel::Full),
+            "3" => Ok(OptLevel::Aggressive),
+            _ => Err(format!("Invalid optimization level '{value}', expected one of: 0, 1, 2, 3")),
+        }
+    }
+}
+
+/// Language extensions recognised while compiling, and how hard to optimize.
+/// Disabled extensions leave their commands as comments.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompileOptions {
+    /// Treat `#` as a tape dump.
+    pub debug_command: bool,
+    /// Treat `@` and `$` as the register commands of Brainfuck Extended.
+    pub extended: bool,
+    /// Treat `:` and `?` as the procedure commands of pbrain.
+    pub procedures: bool,
+    /// Treat `}` and `{` as switching to the next and previous tape.
+    pub multi_tape: bool,
+    /// Reject every byte that is not a command, ASCII whitespace or one of
+    /// these comment bytes, instead of ignoring it.
+    pub strict_ascii: Option<Vec<u8>>,
+    pub opt_level: OptLevel,
+    /// What the program does at the tape ends, which decides whether a run of
+    /// moves may be replaced by its net move.
+    pub tape_end: TapeEnd,
+    /// What `+` and `-` do at the ends of the cell range, which decides
+    /// whether a run of them may be replaced by its net add.
+    pub arithmetic: ArithmeticMode,
+}
+
+impl CompileOptions {
+    /// Only a wrapping tape treats a run of moves like its net move: at any
+    /// other end each move is checked, and may fail or grow, on its own.
+    fn moves_merge(&self) -> bool {
+        self.tape_end == TapeEnd::Wrap
+    }
+
+    /// Likewise only wrapping cells treat a run of adds like its net add, as
+    /// saturating or failing depends on the order of the commands.
+    fn adds_merge(&self) -> bool {
+        self.arithmetic == ArithmeticMode::Wrap
+    }
+}
+
+/// An op together with the byte offset of the source command it came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction {
+    pub op: Op,
+    pub offset: usize,
+    /// How many source commands running the op once stands for, so steps
+    /// are counted the same at every optimization level. `SetZero` and
+    /// `SeekZero` count only their `[` here, as their passes vary.
+    pub commands: u32,
+}
+
+/// Converts a byte offset into `source` to a 1-based (line, column) pair.
+/// Columns count UTF-8 characters, with invalid bytes counting as one each.
+pub fn line_column(source: impl AsRef<[u8]>, offset: usize) -> (usize, usize) {
+    let source = source.as_ref();
+    let before = &source[..offset.min(source.len())];
+    let line = before.iter().filter(|&&byte| byte == b'\n').count() + 1;
+    let line_start = before.iter().rposition(|&byte| byte == b'\n').map_or(0, |index| index + 1);
+    let column = String::from_utf8_lossy(&before[line_start..]).chars().count() + 1;
+    (line, column)
+}
+
+/// Given the index of a `[` in `program`, returns the index of the `]` that
+/// closes it, or `None` if it is never closed or `open_index` is not a `[`.
+pub fn find_matching_bracket(program: &[u8], open_index: usize) -> Option<usize> {
+    if program.get(open_index) != Some(&b'[') {
+        return None;
+    }
+
+    let mut depth = 0usize;
+    for (index, &byte) in program.iter().enumerate().skip(open_index) {
+        match byte {
+            b'[' => depth += 1,
+            b']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(index);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+pub(crate) fn check_brackets(source: &[u8]) -> Result<(), InterpreterError> {
+    let mut open_loops: Vec<usize> = Vec::new();
+
+    for (index, &byte) in source.iter().enumerate() {
+        match byte {
+            b'[' => open_loops.push(index),
+            b']' if open_loops.pop().is_none() => {
+                let (line, column) = line_column(source, index);
+                let (position, file) = (index, None);
+                return Err(InterpreterError::UnmatchedClose { position, line, column, file });
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(&unclosed) = open_loops.first() {
+        let (line, column) = line_column(source, unclosed);
+        let file = None;
+        return Err(InterpreterError::UnmatchedOpen { position: unclosed, line, column, file });
+    }
+    Ok(())
+}
+
+/// Rejects the first byte of `source` that is neither a command enabled by
+/// `options`, ASCII whitespace nor one of `allowed_comments`.
+fn check_strict_ascii(
+    source: &[u8],
+    options: &CompileOptions,
+    allowed_comments: &[u8],
+) -> Result<(), InterpreterError> {
+    let disallowed = source.iter().position(|&byte| {
+        command_op(byte, options).is_none()
+            && !byte.is_ascii_whitespace()
+            && !allowed_comments.contains(&byte)
+    });
+    match disallowed {
+        Some(position) => {
+            let (line, column) = line_column(source, position);
+            let (file, byte) = (None, source[position]);
+            Err(InterpreterError::DisallowedByte { position, line, column, file, byte })
+        }
+        None => Ok(()),
+    }
+}
+
+/// The op for `byte`, or `None` when it is a comment with `options`. Loop
+/// and procedure targets are left for `resolve_loops`, and every `:` is a
+/// `ProcedureStart` until `parse_instructions` pairs them up.
+fn command_op(byte: u8, options: &CompileOptions) -> Option<Op> {
+    let op = match byte {
+        b'+' => Op::Inc,
+        b'-' => Op::Dec,
+        b'>' => Op::Next,
+        b'<' => Op::Prev,
+        b'.' => Op::Print,
+        b',' => Op::Input,
+        b'[' => Op::LoopStart(0),
+        b']' => Op::LoopEnd(0),
+        b'#' if options.debug_command => Op::DebugDump,
+        b'@' if options.extended => Op::StoreRegister,
+        b'$' if options.extended => Op::LoadRegister,
+        b':' if options.procedures => Op::ProcedureStart(0),
+        b'?' if options.procedures => Op::Call,
+        b'}' if options.multi_tape => Op::NextTape,
+        b'{' if options.multi_tape => Op::PreviousTape,
+        _ => return None,
+    };
+    Some(op)
+}
+
+/// Keeps only the eight commands, so comments cost nothing at run time. Each
+/// instruction remembers its offset into the original source. Every second
+/// `:` closes the definition the one before it opened.
+fn parse_instructions(source: &[u8], options: &CompileOptions) -> Vec<Instruction> {
+    let mut instructions: Vec<Instruction> = Vec::new();
+    let mut defining = false;
+
+    for (offset, &byte) in source.iter().enumerate() {
+        if let Some(mut op) = command_op(byte, options) {
+            if let Op::ProcedureStart(_) = op {
+                if defining {
+                    op = Op::ProcedureEnd(0);
+                }
+                defining = !defining;
+            }
+            instructions.push(Instruction { op, offset, commands: 1 });
+        }
+    }
+
+    let comments = source.len() - instructions.len();
+    log!(Level::Debug, "Skipped {comments} comment bytes");
+    instructions
+}
+
+/// Points every loop and procedure op at its partner. Passes that add or
+/// remove ops leave the targets stale, so this runs after them.
+fn resolve_loops(instructions: &mut [Instruction]) {
+    let mut open_loops: Vec<usize> = Vec::new();
+    let mut open_procedures: Vec<usize> = Vec::new();
+
+    for index in 0..instructions.len() {
+        match instructions[index].op {
+            Op::LoopStart(_) => open_loops.push(index),
+            Op::LoopEnd(_) => {
+                let open = open_loops.pop().expect("brackets are checked before compiling");
+                instructions[open].op = Op::LoopStart(index);
+                instructions[index].op = Op::LoopEnd(open);
+            }
+            Op::ProcedureStart(_) => open_procedures.push(index),
+            Op::ProcedureEnd(_) => {
+                let open = open_procedures.pop().expect("procedures are checked before compiling");
+                instructions[open].op = Op::ProcedureStart(index);
+                instructions[index].op = Op::ProcedureEnd(open);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Checks that every `:` opening a definition has one closing it and that no
+/// loop crosses into or out of a procedure, which would leave a loop jump or
+/// a return nowhere to go. Brackets must already be balanced.
+fn check_procedures(source: &[u8]) -> Result<(), InterpreterError> {
+    // The offset of the open definition and the loop depth it opened at.
+    let mut open_procedure: Option<(usize, usize)> = None;
+    let mut depth = 0;
+
+    for (index, &byte) in source.iter().enumerate() {
+        match byte {
+            b'[' => depth += 1,
+            b']' => depth -= 1,
+            b':' => match open_procedure.take() {
+                None => open_procedure = Some((index, depth)),
+                Some((_, open_depth)) if open_depth == depth => {}
+                Some(_) => return Err(unmatched_procedure(source, index, ':')),
+            },
+            _ => {}
+        }
+        if open_procedure.is_some_and(|(_, open_depth)| depth < open_depth) {
+            return Err(unmatched_procedure(source, index, ']'));
+        }
+    }
+
+    match open_procedure {
+        Some((unclosed, _)) => Err(unmatched_procedure(source, unclosed, ':')),
+        None => Ok(()),
+    }
+}
+
+fn unmatched_procedure(source: &[u8], position: usize, command: char) -> InterpreterError {
+    let (line, column) = line_column(source, position);
+    let file = None;
+    InterpreterError::UnmatchedProcedure { position, line, column, file, command }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RunKind {
+    Add,
+    Move,
+}
+
+fn run_delta(op: Op) -> Option<(RunKind, i32)> {
+    match op {
+        Op::Inc => Some((RunKind::Add, 1)),
+        Op::Dec => Some((RunKind::Add, -1)),
+        Op::Add(delta) => Some((RunKind::Add, delta)),
+        Op::Next => Some((RunKind::Move, 1)),
+        Op::Prev => Some((RunKind::Move, -1)),
+        Op::Move(delta) => Some((RunKind::Move, delta)),
+        _ => None,
+    }
+}
+
+/// Adds the commands of ops dropped just before `instruction` to it, as it
+/// runs exactly when they would have.
+fn absorb(instruction: Instruction, dropped: &mut Option<(usize, u32)>) -> Instruction {
+    let commands = dropped.take().map_or(0, |(_, commands)| commands);
+    Instruction { commands: instruction.commands + commands, ..instruction }
+}
+
+/// Coalesces runs of `+`/`-` into `Add` and runs of `>`/`<` into `Move`, each
+/// only when `options` lets them merge. Runs with no net effect
+/// disappear when `drop_no_ops` is set, their commands counted by the next
+/// op, and are otherwise left as they are, like single commands.
+fn merge_runs(
+    instructions: Vec<Instruction>,
+    options: &CompileOptions,
+    drop_no_ops: bool,
+) -> Vec<Instruction> {
+    let mut merged: Vec<Instruction> = Vec::with_capacity(instructions.len());
+    // Where the runs dropped since the last kept op start, and their commands.
+    let mut dropped: Option<(usize, u32)> = None;
+    let mut index = 0;
+
+    while index < instructions.len() {
+        let first = instructions[index];
+        let Some((kind, _)) = run_delta(first.op) else {
+            merged.push(absorb(first, &mut dropped));
+            index += 1;
+            continue;
+        };
+
+        let mut end = index;
+        let mut delta: i32 = 0;
+        while let Some((step_kind, step)) = instructions.get(end).and_then(|next| run_delta(next.op)) {
+            if step_kind != kind {
+                break;
+            }
+            delta = delta.wrapping_add(step);
+            end += 1;
+        }
+
+        let commands = instructions[index..end].iter().map(|run| run.commands).sum();
+        let op = match kind {
+            RunKind::Add if !options.adds_merge() => {
+                merged.extend_from_slice(&instructions[index..end]);
+                index = end;
+                continue;
+            }
+            RunKind::Move if !options.moves_merge() => {
+                merged.extend_from_slice(&instructions[index..end]);
+                index = end;
+                continue;
+            }
+            _ if end - index == 1 => first.op,
+            _ if delta == 0 => {
+                if drop_no_ops {
+                    dropped.get_or_insert((index, 0)).1 += commands;
+                } else {
+                    merged.extend_from_slice(&instructions[index..end]);
+                }
+                index = end;
+                continue;
+            }
+            RunKind::Add => Op::Add(delta),
+            RunKind::Move => Op::Move(delta),
+        };
+        merged.push(absorb(Instruction { op, offset: first.offset, commands }, &mut dropped));
+        index = end;
+    }
+    // Runs dropped at the very end have no op after them to count their
+    // commands, so they stay.
+    if let Some((from, _)) = dropped {
+        merged.extend_from_slice(&instructions[from..]);
+    }
+    merged
+}
+
+/// Replaces a move, an add and the opposite move with a single `AddAt`, so
+/// touching a neighbouring cell doesn't cost two pointer updates.
+fn fold_offset_adds(instructions: Vec<Instruction>) -> Vec<Instruction> {
+    let mut folded: Vec<Instruction> = Vec::with_capacity(instructions.len());
+
+    for instruction in instructions {
+        folded.push(instruction);
+        let tail = folded.len().saturating_sub(3);
+        if let [there, add, back] = folded[tail..] {
+            let deltas = (run_delta(there.op), run_delta(add.op), run_delta(back.op));
+            let (
+                Some((RunKind::Move, offset)),
+                Some((RunKind::Add, delta)),
+                Some((RunKind::Move, returning)),
+            ) = deltas
+            else {
+                continue;
+            };
+            if offset.checked_neg() != Some(returning) {
+                continue;
+            }
+            folded.truncate(tail);
+            let op = Op::AddAt(offset, delta);
+            let commands = there.commands + add.commands + back.commands;
+            folded.push(Instruction { op, offset: there.offset, commands });
+        }
+    }
+    folded
+}
+
+/// Replaces `[-]`, and with `wrap` set also `[+]`, with `SetZero`, and with
+/// `seek` set scan loops such as `[>]` and `[<]` with `SeekZero`. Only loops
+/// whose body and `]` are exactly their own commands are replaced, so every
+/// pass costs steps that follow from the op.
+fn replace_simple_loops(
+    instructions: Vec<Instruction>,
+    wrap: bool,
+    seek: bool,
+) -> Vec<Instruction> {
+    let mut replaced: Vec<Instruction> = Vec::with_capacity(instructions.len());
+
+    for instruction in instructions {
+        replaced.push(instruction);
+        let tail = replaced.len().saturating_sub(3);
+        if let [start, body, end] = replaced[tail..] {
+            if !matches!((start.op, end.op), (Op::LoopStart(_), Op::LoopEnd(_))) {
+                continue;
+            }
+            let op = match body.op {
+                Op::Dec => Op::SetZero(-1),
+                Op::Inc if wrap => Op::SetZero(1),
+                Op::Next if seek => Op::SeekZero(1),
+                Op::Prev if seek => Op::SeekZero(-1),
+                Op::Move(stride) if seek => Op::SeekZero(stride),
+                _ => continue,
+            };
+            let moves = match op {
+                Op::SeekZero(stride) => stride.unsigned_abs(),
+                _ => 1,
+            };
+            if body.commands != moves || end.commands != 1 {
+                continue;
+            }
+            replaced.truncate(tail);
+            replaced.push(Instruction { op, ..start });
+        }
+    }
+    replaced
+}
+
+/// Drops loops, clears and scans that start where the current cell is known
+/// to be zero, so they do nothing: right after another loop, a clear or a scan,
+/// with at most ops in between that neither move the pointer nor write. This
+/// is the only place the cell is known, as a program may run on a tape that
+/// is not empty. A dead loop still takes the steps of its `[`, which the next
+/// kept op counts. Loop targets are left stale.
+fn remove_dead_loops(instructions: Vec<Instruction>) -> Vec<Instruction> {
+    let mut kept: Vec<Instruction> = Vec::with_capacity(instructions.len());
+    // Where the loops dropped since the last kept op start, and their steps.
+    let mut dropped: Option<(usize, u32)> = None;
+    let mut cell_is_zero = false;
+    let mut dead_depth = 0;
+
+    for (index, &instruction) in instructions.iter().enumerate() {
+        if dead_depth > 0 {
+            match instruction.op {
+                Op::LoopStart(_) => dead_depth += 1,
+                Op::LoopEnd(_) => dead_depth -= 1,
+                _ => {}
+            }
+            continue;
+        }
+        cell_is_zero = match instruction.op {
+            Op::LoopStart(_) if cell_is_zero => {
+                dead_depth = 1;
+                dropped.get_or_insert((index, 0)).1 += instruction.commands;
+                continue;
+            }
+            Op::SetZero(_) | Op::SeekZero(_) if cell_is_zero => {
+                dropped.get_or_insert((index, 0)).1 += instruction.commands;
+                continue;
+            }
+            Op::LoopEnd(_) | Op::SetZero(_) | Op::SeekZero(_) => true,
+            Op::Print | Op::DebugDump | Op::StoreRegister => cell_is_zero,
+            _ => false,
+        };
+        kept.push(absorb(instruction, &mut dropped));
+    }
+    // Like `merge_runs`, loops dropped at the very end stay.
+    if let Some((from, _)) = dropped {
+        kept.extend_from_slice(&instructions[from..]);
+    }
+    kept
+}
+
+/// Returns the source offsets of loops that can never end once entered,
+/// because nothing in their body moves the pointer or changes a cell. Loops
+/// that do anything else are never reported, even if they cannot end.
+pub fn find_infinite_loops(instructions: &[Instruction]) -> Vec<usize> {
+    let mut offsets = Vec::new();
+    for (index, instruction) in instructions.iter().enumerate() {
+        let Op::LoopStart(end) = instruction.op else {
+            continue;
+        };
+        let body = &instructions[index + 1..end];
+        let inert = |inner: &Instruction| {
+            matches!(inner.op, Op::Print | Op::DebugDump | Op::StoreRegister)
+        };
+        if body.iter().all(inert) {
+            offsets.push(instruction.offset);
+        }
+    }
+    offsets
+}
+
+/// Compiles `source` into instructions that remember their source offsets.
+/// Any byte that is not a command is a comment, so `source` need not be UTF-8.
+pub fn compile_instructions(
+    source: impl AsRef<[u8]>,
+    options: &CompileOptions,
+) -> Result<Vec<Instruction>, InterpreterError> {
+    let source = source.as_ref();
+    if let Some(allowed_comments) = &options.strict_ascii {
+        check_strict_ascii(source, options, allowed_comments)?;
+    }
+    check_brackets(source)?;
+    if options.procedures {
+        check_procedures(source)?;
+    }
+
+    let mut instructions = parse_instructions(source, options);
+    if options.opt_level >= OptLevel::Basic {
+        let full = options.opt_level >= OptLevel::Full;
+        let wrap = options.moves_merge() && options.adds_merge();
+        instructions = merge_runs(instructions, options, full && wrap);
+        if full && wrap {
+            instructions = fold_offset_adds(instructions);
+        }
+        let seek = full && options.moves_merge();
+        instructions = replace_simple_loops(instructions, options.adds_merge(), seek);
+        if options.opt_level == OptLevel::Aggressive {
+            instructions = remove_dead_loops(instructions);
+        }
+    }
+    resolve_loops(&mut instructions);
+    Ok(instructions)
+}
+
+/// Compiles `source` into ops at the default optimization level, stripping
+/// comments and resolving loop targets.
+pub fn compile(source: impl AsRef<[u8]>) -> Result<Vec<Op>, InterpreterError> {
+    let instructions = compile_instructions(source, &CompileOptions::default())?;
+    Ok(instructions.into_iter().map(|instruction| instruction.op).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compile::{
+        compile, compile_instructions, find_infinite_loops, find_matching_bracket, CompileOptions,
+        Instruction, Op, OptLevel,
+    };
+    use crate::{ArithmeticMode, InterpreterError, TapeEnd};
+
+    fn compile_at(source: &str, opt_level: OptLevel) -> Vec<Op> {
+        let options = CompileOptions { opt_level, ..CompileOptions::default() };
+        let instructions = compile_instructions(source, &options).unwrap();
+        instructions.into_iter().map(|instruction| instruction.op).collect()
+    }
+
+    #[test]
+    fn compile_strips_comments_and_resolves_loops() {
+        assert_eq!(
+            compile("a+[.]").unwrap(),
+            vec![Op::Inc, Op::LoopStart(3), Op::Print, Op::LoopEnd(1)]
+        );
+    }
+
+    #[test]
+    fn compile_resolves_nested_loops() {
+        assert_eq!(
+            compile("[[]]").unwrap(),
+            vec![Op::LoopStart(3), Op::LoopStart(2), Op::LoopEnd(1), Op::LoopEnd(0)]
+        );
+    }
+
+    #[test]
+    fn compile_keeps_source_offsets() {
+        assert_eq!(
+            compile_instructions("x +\n.", &CompileOptions::default()).unwrap(),
+            vec![
+                Instruction { op: Op::Inc, offset: 2, commands: 1 },
+                Instruction { op: Op::Print, offset: 4, commands: 1 },
+            ]
+        );
+    }
+
+    #[test]
+    fn compile_merges_runs_of_adds() {
+        assert_eq!(compile("+++++").unwrap(), vec![Op::Add(5)]);
+        assert_eq!(compile("+++--").unwrap(), vec![Op::Add(1)]);
+    }
+
+    #[test]
+    fn compile_merges_runs_of_moves() {
+        assert_eq!(compile(">><").unwrap(), vec![Op::Move(1)]);
+        assert_eq!(compile("<<<").unwrap(), vec![Op::Move(-3)]);
+    }
+
+    #[test]
+    fn compile_keeps_moves_apart_unless_the_tape_wraps() {
+        let options = CompileOptions { tape_end: TapeEnd::Error, ..CompileOptions::default() };
+        let instructions = compile_instructions(">>+<", &options).unwrap();
+        let ops: Vec<Op> = instructions.into_iter().map(|instruction| instruction.op).collect();
+        assert_eq!(ops, vec![Op::Next, Op::Next, Op::Inc, Op::Prev]);
+    }
+
+    #[test]
+    fn compile_keeps_adds_apart_unless_cells_wrap() {
+        let arithmetic = ArithmeticMode::Saturate;
+        let options = CompileOptions { arithmetic, ..CompileOptions::default() };
+        let instructions = compile_instructions("++[+][-]", &options).unwrap();
+        let ops: Vec<Op> = instructions.into_iter().map(|instruction| instruction.op).collect();
+        assert_eq!(
+            ops,
+            vec![Op::Inc, Op::Inc, Op::LoopStart(4), Op::Inc, Op::LoopEnd(2), Op::SetZero(-1)]
+        );
+    }
+
+    #[test]
+    fn compile_drops_runs_without_net_effect() {
+        assert_eq!(compile_at("+-><.", OptLevel::Full), vec![Op::Print]);
+        assert_eq!(compile("+-.").unwrap(), vec![Op::Inc, Op::Dec, Op::Print]);
+
+        let options = CompileOptions {
+            opt_level: OptLevel::Full,
+            tape_end: TapeEnd::Grow,
+            ..CompileOptions::default()
+        };
+        let instructions = compile_instructions("+-.", &options).unwrap();
+        let ops: Vec<Op> = instructions.into_iter().map(|instruction| instruction.op).collect();
+        assert_eq!(ops, vec![Op::Inc, Op::Dec, Op::Print]);
+    }
+
+    #[test]
+    fn compile_resolves_loops_after_merging() {
+        assert_eq!(
+            compile("++[->>+<<]").unwrap(),
+            vec![
+                Op::Add(2),
+                Op::LoopStart(6),
+                Op::Dec,
+                Op::Move(2),
+                Op::Inc,
+                Op::Move(-2),
+                Op::LoopEnd(1),
+            ]
+        );
+    }
+
+    #[test]
+    fn compile_replaces_clear_loops() {
+        assert_eq!(compile("[-]").unwrap(), vec![Op::SetZero(-1)]);
+        assert_eq!(compile("+[+]>").unwrap(), vec![Op::Inc, Op::SetZero(1), Op::Next]);
+    }
+
+    #[test]
+    fn compile_keeps_other_single_op_loops() {
+        assert_eq!(compile("[--]").unwrap(), vec![Op::LoopStart(2), Op::Add(-2), Op::LoopEnd(0)]);
+        assert_eq!(compile("[.]").unwrap(), vec![Op::LoopStart(2), Op::Print, Op::LoopEnd(0)]);
+    }
+
+    #[test]
+    fn compile_replaces_scan_loops() {
+        assert_eq!(compile_at("[>]", OptLevel::Full), vec![Op::SeekZero(1)]);
+        assert_eq!(compile_at("-[<]", OptLevel::Full), vec![Op::Dec, Op::SeekZero(-1)]);
+        assert_eq!(compile_at("[>>>]", OptLevel::Full), vec![Op::SeekZero(3)]);
+        assert_eq!(compile("[>]").unwrap(), vec![Op::LoopStart(2), Op::Next, Op::LoopEnd(0)]);
+    }
+
+    #[test]
+    fn compile_keeps_moves_and_adds_apart() {
+        assert_eq!(compile(">+<").unwrap(), vec![Op::Next, Op::Inc, Op::Prev]);
+        assert_eq!(compile(">>+++<<").unwrap(), vec![Op::Move(2), Op::Add(3), Op::Move(-2)]);
+    }
+
+    #[test]
+    fn compile_folds_balanced_moves_into_add_at() {
+        assert_eq!(compile_at(">+<", OptLevel::Full), vec![Op::AddAt(1, 1)]);
+        assert_eq!(compile_at(">>+++<<", OptLevel::Full), vec![Op::AddAt(2, 3)]);
+        assert_eq!(compile_at("<-->", OptLevel::Full), vec![Op::AddAt(-1, -2)]);
+        assert_eq!(
+            compile_at("[->+<]", OptLevel::Full),
+            vec![Op::LoopStart(3), Op::Dec, Op::AddAt(1, 1), Op::LoopEnd(0)]
+        );
+        assert_eq!(compile_at(">+<<", OptLevel::Full), vec![Op::Next, Op::Inc, Op::Move(-2)]);
+    }
+
+    #[test]
+    fn compile_without_optimization_keeps_every_command() {
+        assert_eq!(
+            compile_at("++[-]", OptLevel::None),
+            vec![Op::Inc, Op::Inc, Op::LoopStart(4), Op::Dec, Op::LoopEnd(2)]
+        );
+    }
+
+    #[test]
+    fn higher_opt_levels_produce_fewer_ops() {
+        let program = "+++[-]>+-<[>]>>><<.";
+        let none = compile_at(program, OptLevel::None).len();
+        let basic = compile_at(program, OptLevel::Basic).len();
+        let full = compile_at(program, OptLevel::Full).len();
+        assert!(none > basic && basic > full, "{none} {basic} {full}");
+    }
+
+    #[test]
+    fn compile_treats_hash_as_comment_by_default() {
+        assert_eq!(compile("+#").unwrap(), vec![Op::Inc]);
+    }
+
+    #[test]
+    fn compile_recognises_hash_when_enabled() {
+        let options = CompileOptions { debug_command: true, ..CompileOptions::default() };
+
+        let instructions = compile_instructions("+#", &options).unwrap();
+
+        assert_eq!(instructions[1], Instruction { op: Op::DebugDump, offset: 1, commands: 1 });
+    }
+
+    #[test]
+    fn compile_rejects_unbalanced_brackets() {
+        assert!(matches!(compile("]"), Err(InterpreterError::UnmatchedClose { position: 0, .. })));
+    }
+
+    #[test]
+    fn find_matching_bracket_simple() {
+        assert_eq!(find_matching_bracket(b"+[->+<]", 1), Some(6));
+    }
+
+    #[test]
+    fn find_matching_bracket_skips_nested_loops() {
+        let program = b"[[-]>[+]]";
+
+        assert_eq!(find_matching_bracket(program, 0), Some(8));
+        assert_eq!(find_matching_bracket(program, 1), Some(3));
+        assert_eq!(find_matching_bracket(program, 5), Some(7));
+    }
+
+    #[test]
+    fn find_matching_bracket_unmatched() {
+        assert_eq!(find_matching_bracket(b"+[[-]", 1), None);
+        assert_eq!(find_matching_bracket(b"+[-]", 0), None);
+        assert_eq!(find_matching_bracket(b"[", 4), None);
+    }
+
+    #[test]
+    fn find_infinite_loops_flags_loops_that_change_nothing() {
+        let instructions = compile_instructions("+[]>+[.]", &CompileOptions::default()).unwrap();
+
+        assert_eq!(find_infinite_loops(&instructions), vec![1, 5]);
+    }
+
+    #[test]
+    fn find_infinite_loops_ignores_normal_loops() {
+        let source = "++++[>++<-]>[<+>-]+[[-]]";
+        let instructions = compile_instructions(source, &CompileOptions::default()).unwrap();
+
+        assert!(find_infinite_loops(&instructions).is_empty());
+    }
+
+    #[test]
+    fn compile_accepts_invalid_utf8_comments() {
+        assert_eq!(compile(b"\xff+\xc3.").unwrap(), vec![Op::Inc, Op::Print]);
+    }
+
+    #[test]
+    fn register_commands_compile_only_when_extended() {
+        assert_eq!(compile_at("@$", OptLevel::Basic), vec![]);
+
+        let options = CompileOptions { extended: true, ..CompileOptions::default() };
+        let instructions = compile_instructions("@$", &options).unwrap();
+        let ops: Vec<Op> = instructions.into_iter().map(|instruction| instruction.op).collect();
+        assert_eq!(ops, vec![Op::StoreRegister, Op::LoadRegister]);
+    }
+
+    #[test]
+    fn aggressive_removes_loops_that_cannot_run() {
+        assert_eq!(
+            compile_at("+[->+<].[dead .+[nested]][dead]-.", OptLevel::Aggressive),
+            compile_at("+[->+<].-.", OptLevel::Aggressive)
+        );
+        assert_eq!(compile_at("[-][<].", OptLevel::Aggressive), vec![Op::SetZero(-1), Op::Print]);
+        // Nothing would count the steps of a dead loop at the very end.
+        assert_eq!(
+            compile_at("[-][<]", OptLevel::Aggressive),
+            vec![Op::SetZero(-1), Op::SeekZero(-1)]
+        );
+        // The current cell is unknown at the start and after a move.
+        assert_eq!(
+            compile_at("[.]>[.]", OptLevel::Aggressive),
+            vec![
+                Op::LoopStart(2),
+                Op::Print,
+                Op::LoopEnd(0),
+                Op::Next,
+                Op::LoopStart(6),
+                Op::Print,
+                Op::LoopEnd(4)
+            ]
+        );
+    }
+
+    #[test]
+    fn tape_commands_compile_only_with_multiple_tapes() {
+        assert_eq!(compile_at("}{", OptLevel::Basic), vec![]);
+
+        let options = CompileOptions { multi_tape: true, ..CompileOptions::default() };
+        let instructions = compile_instructions("}{", &options).unwrap();
+        let ops: Vec<Op> = instructions.into_iter().map(|instruction| instruction.op).collect();
+        assert_eq!(ops, vec![Op::NextTape, Op::PreviousTape]);
+    }
+}
@@ -0,0 +1,55 @@
+const CELLS_PER_ROW: usize = 16;
+
+fn printable(value: u32) -> char {
+    match value as u8 {
+        byte @ 0x20..=0x7e => byte as char,
+        _ => '.',
+    }
+}
+
+/// Formats `cells[start..start + len]` in the style of `hexdump -C`, wrapping
+/// the cell at `pointer` in brackets. Rows are labelled with logical indices,
+/// counted from the cell at `origin`.
+pub(crate) fn dump(
+    cells: &[u32],
+    hex_width: usize,
+    start: usize,
+    len: usize,
+    pointer: usize,
+    origin: usize,
+) -> String {
+    let start = start.min(cells.len());
+    let end = start.saturating_add(len).min(cells.len());
+    let mut output = String::new();
+
+    for row_start in (start..end).step_by(CELLS_PER_ROW) {
+        let row_end = (row_start + CELLS_PER_ROW).min(end);
+        let label = row_start as isize - origin as isize;
+        if label < 0 {
+            output.push_str(&format!("-{:07x} ", label.unsigned_abs()));
+        } else {
+            output.push_str(&format!("{label:08x} "));
+        }
+
+        for (index, value) in (row_start..).zip(&cells[row_start..row_end]) {
+            let separator = if index == pointer {
+                '['
+            } else if index > row_start && index - 1 == pointer {
+                ']'
+            } else {
+                ' '
+            };
+            output.push(separator);
+            output.push_str(&format!("{value:0hex_width$x}"));
+        }
+        output.push(if row_end - 1 == pointer { ']' } else { ' ' });
+        for _ in row_end..row_start + CELLS_PER_ROW {
+            output.push_str(&" ".repeat(hex_width + 1));
+        }
+
+        output.push_str(" |");
+        output.extend(cells[row_start..row_end].iter().map(|&value| printable(value)));
+        output.push_str("|\n");
+    }
+    output
+}
@@ -0,0 +1,183 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Debug)]
+pub enum InterpreterError {
+    FileNotFound(PathBuf),
+    IoError(io::Error),
+    /// `file` is set when the bracket came from an included file, and `line`
+    /// and `column` are then within that file.
+    UnmatchedOpen { position: usize, line: usize, column: usize, file: Option<PathBuf> },
+    UnmatchedClose { position: usize, line: usize, column: usize, file: Option<PathBuf> },
+    StepLimitExceeded { limit: u64 },
+    Timeout { timeout: Duration },
+    /// Like bracket errors, runtime errors give the line and column of the
+    /// command that failed, and `file` when it came from an included file.
+    /// `command` is the command that moved the pointer.
+    PointerUnderflow {
+        position: usize,
+        line: usize,
+        column: usize,
+        file: Option<PathBuf>,
+        command: char,
+    },
+    PointerOverflow {
+        position: usize,
+        line: usize,
+        column: usize,
+        file: Option<PathBuf>,
+        command: char,
+    },
+    CellOverflow { position: usize, line: usize, column: usize, file: Option<PathBuf> },
+    InvalidPointerWrap { wrap: usize, tape_size: usize },
+    InvalidCellModulus { modulus: u32, max: u64 },
+    CellOutOfBounds { index: isize, tape_size: usize },
+    CellValueOutOfRange { value: u32, max: u32 },
+    Interrupted { steps: u64 },
+    /// A pbrain `:` opening a definition that is never closed or closing it
+    /// inside a loop, or a `]` that leaves the procedure its loop started in.
+    UnmatchedProcedure {
+        position: usize,
+        line: usize,
+        column: usize,
+        file: Option<PathBuf>,
+        command: char,
+    },
+    UndefinedProcedure {
+        id: u32,
+        position: usize,
+        line: usize,
+        column: usize,
+        file: Option<PathBuf>,
+    },
+    CallDepthExceeded {
+        limit: usize,
+        position: usize,
+        line: usize,
+        column: usize,
+        file: Option<PathBuf>,
+    },
+    /// A byte rejected by strict ASCII validation.
+    DisallowedByte {
+        position: usize,
+        line: usize,
+        column: usize,
+        file: Option<PathBuf>,
+        byte: u8,
+    },
+    InvalidState { reason: String },
+    InvalidNumber { input: String },
+    IncludeCycle { path: PathBuf },
+    IncludeTooDeep { path: PathBuf },
+}
+
+impl fmt::Display for InterpreterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InterpreterError::FileNotFound(path) => write!(f, "File {path:?} does not exist"),
+            InterpreterError::IoError(err) => write!(f, "I/O error: {err}"),
+            InterpreterError::UnmatchedOpen { line, column, file, .. } => {
+                write_location(f, file, *line, *column)?;
+                write!(f, "unmatched '['")
+            }
+            InterpreterError::UnmatchedClose { line, column, file, .. } => {
+                write_location(f, file, *line, *column)?;
+                write!(f, "unmatched ']'")
+            }
+            InterpreterError::StepLimitExceeded { limit } => {
+                write!(f, "Execution stopped after reaching the limit of {limit} steps")
+            }
+            InterpreterError::Timeout { timeout } => {
+                write!(f, "Execution stopped after the timeout of {} ms", timeout.as_millis())
+            }
+            InterpreterError::PointerUnderflow { position, line, column, file, command } => {
+                write_location(f, file, *line, *column)?;
+                write!(f, "'{command}' at char {position} moved the pointer left of the first cell")
+            }
+            InterpreterError::PointerOverflow { position, line, column, file, command } => {
+                write_location(f, file, *line, *column)?;
+                write!(f, "'{command}' at char {position} moved the pointer right of the last cell")
+            }
+            InterpreterError::CellOverflow { position, line, column, file } => {
+                write_location(f, file, *line, *column)?;
+                write!(f, "command at char {position} took the cell out of range")
+            }
+            InterpreterError::InvalidPointerWrap { wrap, tape_size } => {
+                write!(f, "Pointer wrap {wrap} must be between 1 and the tape size {tape_size}")
+            }
+            InterpreterError::InvalidCellModulus { modulus, max } => {
+                write!(f, "Cell modulus {modulus} must be between 1 and {max}")
+            }
+            InterpreterError::CellOutOfBounds { index, tape_size } => {
+                write!(f, "Cell {index} is outside the tape of {tape_size} cells")
+            }
+            InterpreterError::CellValueOutOfRange { value, max } => {
+                write!(f, "Value {value} does not fit in a cell, the maximum is {max}")
+            }
+            InterpreterError::Interrupted { steps } => {
+                write!(f, "Execution interrupted after {steps} steps")
+            }
+            InterpreterError::UnmatchedProcedure { line, column, file, command, .. } => {
+                write_location(f, file, *line, *column)?;
+                write!(f, "unmatched '{command}'")
+            }
+            InterpreterError::UndefinedProcedure { id, position, line, column, file } => {
+                write_location(f, file, *line, *column)?;
+                write!(f, "'?' at char {position} called procedure {id}, which is not defined")
+            }
+            InterpreterError::CallDepthExceeded { limit, position, line, column, file } => {
+                write_location(f, file, *line, *column)?;
+                write!(f, "'?' at char {position} nested procedure calls deeper than {limit}")
+            }
+            InterpreterError::DisallowedByte { position, line, column, file, byte } => {
+                write_location(f, file, *line, *column)?;
+                write!(
+                    f,
+                    "byte 0x{byte:02x} at char {position} is not a command, whitespace or an \
+                     allowed comment"
+                )
+            }
+            InterpreterError::InvalidNumber { input } => {
+                write!(f, "Expected a decimal number as input, got '{input}'")
+            }
+            InterpreterError::InvalidState { reason } => {
+                write!(f, "Invalid interpreter state: {reason}")
+            }
+            InterpreterError::IncludeCycle { path } => {
+                write!(f, "File {path:?} includes itself")
+            }
+            InterpreterError::IncludeTooDeep { path } => {
+                write!(f, "Including {path:?} nests includes too deeply")
+            }
+        }
+    }
+}
+
+fn write_location(
+    f: &mut fmt::Formatter<'_>,
+    file: &Option<PathBuf>,
+    line: usize,
+    column: usize,
+) -> fmt::Result {
+    match file {
+        Some(file) => write!(f, "error in {} at line {line}, column {column}: ", file.display()),
+        None => write!(f, "error at line {line}, column {column}: "),
+    }
+}
+
+impl std::error::Error for InterpreterError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            InterpreterError::IoError(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for InterpreterError {
+    fn from(err: io::Error) -> Self {
+        InterpreterError::IoError(err)
+    }
+}
@@ -0,0 +1,37 @@
+/// Cost of the fixed part of a multiplication loop: `>`, `[<`, `>-]<`.
+const LOOP_OVERHEAD: usize = 7;
+
+/// Produces a program that prints `text`. The current cell is reused for
+/// every byte, moving it by the shorter way round to the next value. Large
+/// moves use a multiplication loop over the cell to its right, which is left
+/// at zero.
+pub fn generate(text: &str) -> String {
+    let mut program = String::new();
+    let mut current = 0u8;
+    for byte in text.bytes() {
+        let up = byte.wrapping_sub(current) as usize;
+        let (delta, command) = if up <= 128 { (up, '+') } else { (256 - up, '-') };
+        program.push_str(&adjust(delta, command));
+        program.push('.');
+        current = byte;
+    }
+    program
+}
+
+/// Applies `command` `delta` times, using a loop when that is shorter.
+fn adjust(delta: usize, command: char) -> String {
+    let repeat = |count: usize, command: char| command.to_string().repeat(count);
+
+    let best_loop = (2..=delta)
+        .map(|factor| (factor, delta / factor, delta % factor))
+        .min_by_key(|&(factor, times, rest)| factor + times + rest);
+    match best_loop {
+        Some((factor, times, rest)) if factor + times + rest + LOOP_OVERHEAD < delta => format!(
+            ">{}[<{}>-]<{}",
+            repeat(times, '+'),
+            repeat(factor, command),
+            repeat(rest, command)
+        ),
+        _ => repeat(delta, command),
+    }
+}
@@ -0,0 +1,106 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::compile::line_column;
+use crate::InterpreterError;
+
+/// Directive that splices another file into a program, e.g. `@include "lib.bf"`.
+/// It has to be alone on its line, and it is removed together with the line
+/// so the `.` of a file name never reaches the program.
+const INCLUDE_DIRECTIVE: &[u8] = b"@include";
+
+/// How deeply includes may nest before the chain is assumed to be runaway.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// A stretch of the expanded program copied verbatim from one file, starting
+/// at the beginning of line `line` of `path`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub path: PathBuf,
+    pub line: usize,
+}
+
+/// Appends `program`, read from `path`, to `out` with its includes expanded.
+/// Included paths are relative to the file that includes them. Returns where
+/// every part of the appended program came from, or nothing when `program`
+/// has no includes, since its offsets then map onto the file directly.
+pub(crate) fn expand(
+    path: &Path,
+    program: &[u8],
+    out: &mut Vec<u8>,
+) -> Result<Vec<Span>, InterpreterError> {
+    let mut expander = Expander { out, spans: Vec::new(), stack: Vec::new(), included: false };
+    let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    expander.stack.push(canonical);
+    expander.expand(path, program)?;
+    Ok(if expander.included { expander.spans } else { Vec::new() })
+}
+
+/// Maps `offset` in the expanded `program` back to the file it came from,
+/// as that file's path, line and column.
+pub(crate) fn locate<'a>(
+    spans: &'a [Span],
+    program: &[u8],
+    offset: usize,
+) -> Option<(&'a Path, usize, usize)> {
+    let span = spans.iter().find(|span| (span.start..span.end).contains(&offset))?;
+    let (line, column) = line_column(&program[span.start..span.end], offset - span.start);
+    Some((&span.path, span.line + line - 1, column))
+}
+
+struct Expander<'a> {
+    out: &'a mut Vec<u8>,
+    spans: Vec<Span>,
+    /// Canonical paths of the files being expanded, outermost first.
+    stack: Vec<PathBuf>,
+    included: bool,
+}
+
+impl Expander<'_> {
+    fn expand(&mut self, path: &Path, program: &[u8]) -> Result<(), InterpreterError> {
+        let mut start = self.out.len();
+        let mut start_line = 1;
+        for (index, line) in program.split_inclusive(|&byte| byte == b'\n').enumerate() {
+            let Some(name) = directive(line) else {
+                self.out.extend_from_slice(line);
+                continue;
+            };
+            self.included = true;
+            self.close_span(path, start, start_line);
+
+            let target = path.parent().unwrap_or(Path::new("")).join(name);
+            let canonical = fs::canonicalize(&target)
+                .map_err(|_| InterpreterError::FileNotFound(target.clone()))?;
+            if self.stack.contains(&canonical) {
+                return Err(InterpreterError::IncludeCycle { path: target });
+            }
+            if self.stack.len() > MAX_INCLUDE_DEPTH {
+                return Err(InterpreterError::IncludeTooDeep { path: target });
+            }
+            let included = fs::read(&target)?;
+            self.stack.push(canonical);
+            self.expand(&target, &included)?;
+            self.stack.pop();
+
+            start = self.out.len();
+            start_line = index + 2;
+        }
+        self.close_span(path, start, start_line);
+        Ok(())
+    }
+
+    fn close_span(&mut self, path: &Path, start: usize, line: usize) {
+        if self.out.len() > start {
+            let path = path.to_path_buf();
+            self.spans.push(Span { start, end: self.out.len(), path, line });
+        }
+    }
+}
+
+/// The file name of an include directive line, if `line` is one.
+fn directive(line: &[u8]) -> Option<&str> {
+    let rest = line.trim_ascii().strip_prefix(INCLUDE_DIRECTIVE)?.trim_ascii();
+    let name = rest.strip_prefix(b"\"")?.strip_suffix(b"\"")?;
+    std::str::from_utf8(name).ok().filter(|name| !name.is_empty())
+}
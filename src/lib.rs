@@ -0,0 +1,4652 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, stderr, stdin, stdout, BufRead, Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use flate2::read::GzDecoder;
+use memmap2::Mmap;
+use serde_json::{json, Value};
+
+mod ascii;
+mod batch;
+mod buffering;
+mod builder;
+mod compile;
+mod dump;
+mod error;
+mod generate;
+mod include;
+mod minimize;
+mod profile;
+mod random;
+mod steps;
+mod transpile;
+mod utf8;
+mod visualize;
+mod wav;
+
+pub use ascii::AsciiWriter;
+pub use batch::run_batch;
+pub use buffering::BufferedOutput;
+pub use builder::InterpreterBuilder;
+pub use compile::{
+    compile, compile_instructions, find_infinite_loops, find_matching_bracket, line_column,
+    CompileOptions, Instruction, Op, OptLevel,
+};
+pub use error::InterpreterError;
+pub use generate::generate;
+pub use minimize::minimize;
+pub use profile::{group_digits, Profile};
+pub use random::SeededInput;
+pub use steps::{ExecutedStep, Steps};
+pub use utf8::Utf8Writer;
+pub use wav::WavWriter;
+
+pub const DEFAULT_TAPE_SIZE: usize = 30000;
+
+/// Line that ends a program read by `load_until_sentinel`.
+pub const PROGRAM_SENTINEL: &str = "%%";
+
+/// First two bytes of every gzip stream.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// How many commands run between checks of the wall-clock timeout.
+const TIMEOUT_CHECK_INTERVAL: u64 = 1024;
+
+/// Largest tape `import_state` accepts beyond the current one, so a corrupt
+/// snapshot can't make it allocate without bound.
+const MAX_IMPORTED_TAPE_SIZE: usize = 1 << 24;
+
+/// How many bytes at a time the `[.,]` fast path copies from input to output.
+const COPY_BLOCK_SIZE: usize = 8192;
+
+/// How often a loop that can never end looks at the interrupt flag.
+const INTERRUPT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How deeply pbrain procedure calls may nest, which also bounds recursion.
+pub const MAX_CALL_DEPTH: usize = 4096;
+
+/// Number of cells shown by the `#` debug command.
+const DEBUG_DUMP_WINDOW: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellWidth {
+    Eight,
+    Sixteen,
+    ThirtyTwo,
+}
+
+impl CellWidth {
+    pub fn max_value(&self) -> u32 {
+        match self {
+            CellWidth::Eight => u8::MAX as u32,
+            CellWidth::Sixteen => u16::MAX as u32,
+            CellWidth::ThirtyTwo => u32::MAX,
+        }
+    }
+
+    /// Number of hex digits needed to show a cell of this width.
+    pub fn hex_digits(&self) -> usize {
+        match self {
+            CellWidth::Eight => 2,
+            CellWidth::Sixteen => 4,
+            CellWidth::ThirtyTwo => 8,
+        }
+    }
+
+    /// Number of bytes in a cell of this width.
+    pub fn bytes(&self) -> usize {
+        match self {
+            CellWidth::Eight => 1,
+            CellWidth::Sixteen => 2,
+            CellWidth::ThirtyTwo => 4,
+        }
+    }
+
+    /// Reinterprets a stored cell value as a two's complement number of this width.
+    pub fn to_signed(&self, value: u32) -> i64 {
+        match self {
+            CellWidth::Eight => value as u8 as i8 as i64,
+            CellWidth::Sixteen => value as u16 as i16 as i64,
+            CellWidth::ThirtyTwo => value as i32 as i64,
+        }
+    }
+}
+
+/// What `,` stores in the current cell once the input is exhausted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EofBehavior {
+    Zero,
+    MinusOne,
+    Unchanged,
+}
+
+/// What happens when the pointer moves past either end of the tape.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TapeEnd {
+    /// Jump to the opposite end of the tape.
+    #[default]
+    Wrap,
+    /// Append a new cell on the right; moving left of cell 0 is an error.
+    Grow,
+    /// Moving past either end is an error.
+    Error,
+    /// Grow on whichever side the pointer leaves, for an unbounded tape in
+    /// both directions. Cells left of the start get negative logical indices,
+    /// see [`Interpreter::pointer`].
+    GrowBoth,
+}
+
+/// Option sets that match well-known interpreters, applied with
+/// [`InterpreterBuilder::dialect`]. All of them use 30000 cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    /// The common convention: 8-bit wrapping cells, EOF stores 0 and the
+    /// pointer wraps around the ends of the tape.
+    Classic,
+    /// Urban Müller's original interpreter: like `Classic`, but EOF leaves
+    /// the cell unchanged.
+    Urban,
+    /// pbrain: `Classic` plus the procedure commands `:` and `?`, see
+    /// [`Interpreter::set_procedures`]. Unlike Paul M. Parks' original, which
+    /// wraps definitions in `(` and `)` and calls with `:`, a pair of `:`
+    /// wraps a definition and `?` calls.
+    Pbrain,
+}
+
+impl FromStr for Dialect {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "classic" => Ok(Dialect::Classic),
+            "urban" => Ok(Dialect::Urban),
+            "pbrain" => Ok(Dialect::Pbrain),
+            _ => Err(format!(
+                "Invalid dialect '{value}', expected one of: classic, urban, pbrain"
+            )),
+        }
+    }
+}
+
+/// How the command-line interpreter turns the bytes printed by `.` into
+/// text, see [`AsciiWriter`] and [`Utf8Writer`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputMode {
+    /// Write every byte as is.
+    #[default]
+    Raw,
+    /// Replace bytes above 127 with a placeholder.
+    Ascii,
+    /// Decode the bytes as UTF-8, replacing invalid sequences.
+    Utf8,
+}
+
+impl FromStr for OutputMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "raw" => Ok(OutputMode::Raw),
+            "ascii" => Ok(OutputMode::Ascii),
+            "utf8" => Ok(OutputMode::Utf8),
+            _ => Err(format!("Invalid output mode '{value}', expected one of: raw, ascii, utf8")),
+        }
+    }
+}
+
+/// When the command-line interpreter flushes the output, see
+/// [`BufferedOutput`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputBuffering {
+    /// Flush after every byte, for interactive programs.
+    None,
+    /// Flush after every newline.
+    #[default]
+    Line,
+    /// Flush only before reading input and at the end, for batch programs.
+    Full,
+}
+
+impl FromStr for OutputBuffering {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "none" => Ok(OutputBuffering::None),
+            "line" => Ok(OutputBuffering::Line),
+            "full" => Ok(OutputBuffering::Full),
+            _ => Err(format!(
+                "Invalid output buffering '{value}', expected one of: none, line, full"
+            )),
+        }
+    }
+}
+
+/// What `+` and `-` do when the current cell would leave `0..=max`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ArithmeticMode {
+    /// Wrap around modulo the cell size.
+    #[default]
+    Wrap,
+    /// Stay at 0 or the maximum value.
+    Saturate,
+    /// Fail with `InterpreterError::CellOverflow`.
+    Error,
+}
+
+impl FromStr for ArithmeticMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "wrap" => Ok(ArithmeticMode::Wrap),
+            "saturate" => Ok(ArithmeticMode::Saturate),
+            "error" => Ok(ArithmeticMode::Error),
+            _ => Err(format!(
+                "Invalid arithmetic mode '{value}', expected one of: wrap, saturate, error"
+            )),
+        }
+    }
+}
+
+impl FromStr for TapeEnd {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "wrap" => Ok(TapeEnd::Wrap),
+            "grow" => Ok(TapeEnd::Grow),
+            "error" => Ok(TapeEnd::Error),
+            "grow-both" => Ok(TapeEnd::GrowBoth),
+            _ => Err(format!(
+                "Invalid tape end '{value}', expected one of: wrap, grow, error, grow-both"
+            )),
+        }
+    }
+}
+
+/// Replaces a leading `#!` line with spaces. Blanking rather than removing it
+/// keeps the offsets, lines and columns of errors pointing into the file.
+fn blank_shebang(mut program: Vec<u8>) -> Vec<u8> {
+    if program.starts_with(b"#!") {
+        let line_end = program.iter().position(|&byte| byte == b'\n').unwrap_or(program.len());
+        program[..line_end].fill(b' ');
+    }
+    program
+}
+
+/// Number of `cells` up to and including the last non-zero one or `pointer`,
+/// whichever is further right.
+fn used_len(cells: &[u32], pointer: usize) -> usize {
+    let last_non_zero = cells.iter().rposition(|&cell| cell != 0).unwrap_or(0);
+    last_non_zero.max(pointer) + 1
+}
+
+/// A change to a watched cell, reported by [`Interpreter::take_watch_hit`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    /// Logical index of the cell, like [`Interpreter::peek`] takes.
+    pub cell: isize,
+    pub old: u32,
+    pub new: u32,
+    /// Source offset of the command that changed the cell.
+    pub offset: usize,
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "watchpoint: cell {} changed from {} to {} at char {}",
+            self.cell, self.old, self.new, self.offset
+        )
+    }
+}
+
+/// Where [`Interpreter::load`] reads a program from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProgramSource {
+    /// A file, handled like [`Interpreter::load_file`].
+    File(PathBuf),
+    /// Standard input, read to the end like [`Interpreter::load_stdin`].
+    Stdin,
+    /// A program already in memory.
+    Bytes(Vec<u8>),
+}
+
+/// How [`Interpreter::resume`] stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunStatus {
+    /// The program ran to its end.
+    Finished,
+    /// Execution stopped before the op at breakpoint `offset`.
+    Paused { offset: usize },
+}
+
+/// What the program has done with a cell, tracked for `write_only_cells`.
+#[derive(Debug, Clone, Copy, Default)]
+struct CellUsage {
+    written: bool,
+    printed: bool,
+}
+
+/// Everything a [`Interpreter::try_run_string`] run produced.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunResult {
+    pub output: Vec<u8>,
+    /// The cells up to the last non-zero one or the pointer, as `u32` like
+    /// [`Interpreter::tape`] so wide cells are not truncated.
+    pub tape_snapshot: Vec<u32>,
+    /// Logical index of the current cell, like [`Interpreter::pointer`].
+    pub pointer: isize,
+    pub steps: u64,
+}
+
+/// Output for [`Interpreter::try_run_string`] that can be read back after
+/// the interpreter had it.
+#[derive(Clone, Default)]
+pub(crate) struct CapturedOutput(pub(crate) Rc<RefCell<Vec<u8>>>);
+
+impl Write for CapturedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// What one step changed, for [`Interpreter::step_back`].
+#[derive(Debug, Clone, Copy)]
+struct Undo {
+    tape: usize,
+    pointer: usize,
+    /// The cell the op could write and its value before.
+    cell: Option<(usize, u32)>,
+    register: u32,
+    instruction_pointer: usize,
+    steps: u64,
+    /// The depth of the call stack and its top entry, which a call or a
+    /// return changes.
+    call_depth: usize,
+    call_top: Option<usize>,
+    /// The procedure a `:` defined and its definition before.
+    definition: Option<(u32, Option<usize>)>,
+}
+
+/// A tape other than the active one, parked with its pointer while
+/// another is in use.
+#[derive(Debug)]
+struct Tape {
+    cells: Vec<u32>,
+    cell_index: usize,
+    origin: usize,
+}
+
+pub struct InterpreterState {
+    cells: Vec<u32>,
+    cell_width: CellWidth,
+    eof_behavior: EofBehavior,
+    signed_cells: bool,
+    wide_input: bool,
+    bang_input: bool,
+    count_only: bool,
+    crlf_output: bool,
+    numeric_io: bool,
+    cell_usage: Option<Vec<CellUsage>>,
+    max_steps: Option<u64>,
+    timeout: Option<Duration>,
+    interrupt: Option<Arc<AtomicBool>>,
+    started_at: Instant,
+    tape_end: TapeEnd,
+    pointer_wrap: Option<usize>,
+    arithmetic: ArithmeticMode,
+    cell_modulus: Option<u32>,
+    register: u32,
+    /// pbrain procedures by number, as the index of their `ProcedureStart`.
+    procedures: HashMap<u32, usize>,
+    /// Index of the `Call` every running procedure returns to.
+    call_stack: Vec<usize>,
+    compile_options: CompileOptions,
+    cell_index: usize,
+    origin: usize,
+    /// Every tape of the multi-tape mode, empty with a single tape. The
+    /// active one is in `cells`, leaving only an empty slot here.
+    tapes: Vec<Tape>,
+    active_tape: usize,
+    view_offset: Option<isize>,
+    file_content: Vec<u8>,
+    source_map: Vec<include::Span>,
+    instructions: Vec<Instruction>,
+    instruction_pointer: usize,
+    watchpoints: Vec<isize>,
+    watch_hit: Option<WatchHit>,
+    history: VecDeque<Undo>,
+    history_limit: usize,
+    breakpoints: Vec<usize>,
+    pending_line: String,
+    input: Box<dyn Read>,
+    input_queue: VecDeque<u8>,
+    output: Box<dyn Write>,
+    debug_output: Box<dyn Write>,
+    trace: Option<Box<dyn Write>>,
+    profile: Profile,
+}
+
+impl fmt::Debug for InterpreterState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InterpreterState")
+            .field("cells", &self.cells)
+            .field("cell_width", &self.cell_width)
+            .field("eof_behavior", &self.eof_behavior)
+            .field("signed_cells", &self.signed_cells)
+            .field("wide_input", &self.wide_input)
+            .field("bang_input", &self.bang_input)
+            .field("count_only", &self.count_only)
+            .field("crlf_output", &self.crlf_output)
+            .field("numeric_io", &self.numeric_io)
+            .field("analyze", &self.cell_usage.is_some())
+            .field("max_steps", &self.max_steps)
+            .field("timeout", &self.timeout)
+            .field("interrupt", &self.interrupt)
+            .field("tape_end", &self.tape_end)
+            .field("pointer_wrap", &self.pointer_wrap)
+            .field("arithmetic", &self.arithmetic)
+            .field("cell_modulus", &self.cell_modulus)
+            .field("register", &self.register)
+            .field("procedures", &self.procedures)
+            .field("call_stack", &self.call_stack)
+            .field("compile_options", &self.compile_options)
+            .field("cell_index", &self.cell_index)
+            .field("origin", &self.origin)
+            .field("tapes", &self.tapes)
+            .field("active_tape", &self.active_tape)
+            .field("view_offset", &self.view_offset)
+            .field("file_content", &String::from_utf8_lossy(&self.file_content))
+            .field("source_map", &self.source_map)
+            .field("instructions", &self.instructions)
+            .field("instruction_pointer", &self.instruction_pointer)
+            .field("watchpoints", &self.watchpoints)
+            .field("watch_hit", &self.watch_hit)
+            .field("history", &self.history.len())
+            .field("history_limit", &self.history_limit)
+            .field("breakpoints", &self.breakpoints)
+            .field("pending_line", &self.pending_line)
+            .field("input_queue", &self.input_queue)
+            .field("tracing", &self.trace.is_some())
+            .field("profile", &self.profile)
+            .finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for InterpreterState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let non_zero = self.cells.iter().filter(|&&cell| cell != 0).count();
+        write!(
+            f,
+            "pointer: {}, cell: {}, non-zero cells: {}, program: {} bytes",
+            self.pointer(),
+            self.display_value(self.cell_index),
+            non_zero,
+            self.file_content.len()
+        )
+    }
+}
+
+
+pub trait Interpreter {
+    fn new() -> Self;
+
+    /// A tape of `tape_size` cells. A tape always has at least one cell, so
+    /// a size of 0 gives a single cell.
+    fn with_tape_size(tape_size: usize) -> Self;
+
+    /// Like `with_tape_size`, with cells of `cell_width`.
+    fn with_config(tape_size: usize, cell_width: CellWidth) -> Self;
+
+    /// Like `with_config`, but in `TapeEnd::Wrap` mode the pointer wraps at
+    /// `pointer_wrap` instead of the tape length, confining it to a window at
+    /// the start of the tape. Fails if the boundary is zero or larger than
+    /// the tape.
+    fn with_pointer_wrap(
+        tape_size: usize,
+        cell_width: CellWidth,
+        pointer_wrap: Option<usize>,
+    ) -> Result<Self, InterpreterError>
+    where
+        Self: Sized;
+
+    /// Appends the contents of `filename` to the loaded program, so several
+    /// files can be run in sequence against one tape. Gzip-compressed files
+    /// are decompressed transparently, and a leading `#!` line is ignored so
+    /// that programs can be made executable. A line holding only
+    /// `@include "lib.bf"` is replaced by that file, found relative to the
+    /// including one, and bracket errors then name the file they are in.
+    fn load_file(&mut self, filename: &str) -> Result<(), InterpreterError>;
+    fn load_string(&mut self, program: &str);
+
+    /// Appends the program from `source` to the loaded program. Unlike
+    /// `load_bytes`, in-memory programs are appended too, so any mix of
+    /// sources can be run in sequence against one tape.
+    fn load(&mut self, source: ProgramSource) -> Result<(), InterpreterError>;
+
+    /// Compiles and runs `filename` straight from a memory map instead of
+    /// reading it into memory first, which suits very large generated
+    /// programs. The file is not kept as the loaded program, and it is used
+    /// as is: no gzip, `#!` or `!` input handling.
+    fn run_file_mapped(&mut self, filename: &str) -> Result<(), InterpreterError>;
+
+    /// Like `load_string`, for programs that are not valid UTF-8. Every byte
+    /// that is not a command is a comment.
+    fn load_bytes(&mut self, program: &[u8]);
+
+    /// Reads stdin to the end and appends it to the loaded program. Since the
+    /// whole stream is consumed before execution starts, `,` will only see EOF.
+    fn load_stdin(&mut self) -> Result<(), InterpreterError>;
+
+    /// Appends lines from `source` up to a line containing only
+    /// [`PROGRAM_SENTINEL`], then keeps the rest of `source` as the input for
+    /// `,`. This lets one stream carry both the program and its input.
+    fn load_until_sentinel(&mut self, source: Box<dyn BufRead>) -> Result<(), InterpreterError>;
+
+    /// Checks that every bracket of the loaded program has a partner,
+    /// reporting the byte offset of the first one that doesn't.
+    fn validate(&self) -> Result<(), InterpreterError>;
+
+    /// Compiles the loaded program and rewinds execution to its first op.
+    fn prepare(&mut self) -> Result<(), InterpreterError>;
+
+    /// The ops produced by the last `prepare`, each with the source offset of
+    /// the command it starts at.
+    fn instructions(&self) -> &[Instruction];
+
+    /// Maps every op of the last `prepare` to the source command it starts
+    /// at, as a JSON array of `{"op", "offset", "line", "column"}` objects in
+    /// op order, for front ends that highlight the running command. Ops from
+    /// an included file also carry its `"file"`, and their line and column are
+    /// within it.
+    fn source_map(&self) -> Value;
+    fn parse(&mut self) -> Result<(), InterpreterError>;
+
+    /// Executes the next command of the loaded program and returns it.
+    /// Returns `None` once the end of the program is reached. `prepare` has
+    /// to be called before the first step.
+    fn step(&mut self) -> Result<Option<char>, InterpreterError>;
+
+    /// Like `step`, but when the next command opens a loop the whole loop is
+    /// run before returning. The step limit still applies inside the loop.
+    fn step_over(&mut self) -> Result<Option<char>, InterpreterError>;
+
+    /// Keeps what the last `limit` steps changed so `step_back` can undo
+    /// them. Zero, the default, keeps nothing.
+    fn set_history_limit(&mut self, limit: usize);
+
+    /// Undoes the last step: the pointer, the cell it changed, the register,
+    /// the procedure calls and definitions and the position in the program go
+    /// back to how they were. Output that
+    /// was written and input that was read stay as they are. Returns `false`
+    /// when there is no step left to undo.
+    fn step_back(&mut self) -> bool;
+
+    /// Watches `cell` for changes. When a step changes it, the change is
+    /// kept for `take_watch_hit`, and `parse` writes it to the debug output.
+    fn add_watchpoint(&mut self, cell: isize);
+
+    /// The watched cell change made by the last step, if any.
+    fn take_watch_hit(&mut self) -> Option<WatchHit>;
+
+    /// Makes `resume` stop before the op starting at source `offset`. Runs
+    /// of commands are merged into one op, so only the first command of a
+    /// run can be a breakpoint.
+    fn add_breakpoint(&mut self, offset: usize);
+
+    /// Runs from the current op until the program ends or reaches a
+    /// breakpoint. The op it starts at is never treated as a breakpoint, so
+    /// calling it again after a pause carries on past it. `prepare` has to
+    /// be called before the first call.
+    fn resume(&mut self) -> Result<RunStatus, InterpreterError>;
+
+    /// Snapshots the tapes and their pointers, the register, the defined
+    /// procedures and running calls, the position in the program and the step
+    /// count as JSON, so a paused run can be continued later with
+    /// `import_state`. Cells after the last non-zero one are left out.
+    fn export_state(&self) -> String;
+
+    /// Restores a snapshot from `export_state`. The same program has to be
+    /// loaded and prepared first, as the snapshot only records a position in
+    /// it, with as many tapes; `resume` then carries on where the snapshot was
+    /// taken. Each tape may be at most 16M cells, or as long as the current
+    /// one if longer.
+    fn import_state(&mut self, json: &str) -> Result<(), InterpreterError>;
+
+    /// Loads `program` and runs it to completion.
+    ///
+    /// ```
+    /// use beefin::{Interpreter, InterpreterState};
+    ///
+    /// let mut interpreter = InterpreterState::new();
+    /// interpreter.run_string("+++.").unwrap();
+    /// ```
+    fn run_string(&mut self, program: &str) -> Result<(), InterpreterError>;
+    fn run_bytes(&mut self, program: &[u8]) -> Result<(), InterpreterError>;
+
+    /// Runs `program` with `input` as everything `,` can read, then collects
+    /// the output and the final state. The configured input and output are
+    /// left in place for later runs, and the tape is not reset beforehand.
+    fn try_run_string(
+        &mut self,
+        program: &str,
+        input: &[u8],
+    ) -> Result<RunResult, InterpreterError>;
+
+    /// Runs a snippet against the current tape without resetting it. A snippet
+    /// with unclosed loops is buffered until a later line closes them.
+    fn eval_line(&mut self, line: &str) -> Result<(), InterpreterError>;
+    fn is_line_pending(&self) -> bool;
+
+    /// Logical index of the current cell. Logical indices count from the
+    /// cell the pointer started on, so they stay put when a
+    /// `TapeEnd::GrowBoth` tape grows to the left, where they go negative.
+    fn pointer(&self) -> isize;
+    fn current_cell(&self) -> u32;
+
+    /// The whole tape. Cells are stored as `u32` whatever the cell width, so
+    /// every value fits in the configured [`CellWidth`]. Logical cell 0 is
+    /// at [`Interpreter::origin`].
+    fn tape(&self) -> &[u32];
+
+    /// The value of the cell at logical `index`, or `None` past either end of
+    /// the tape. Like `tape`, values are `u32` so that wide cells fit.
+    fn peek(&self, index: isize) -> Option<u32>;
+
+    /// Overwrites the cell at logical `index`, for setting up a tape in tests
+    /// or a debugger. Fails past either end of the tape or when `value`
+    /// doesn't fit the cell.
+    fn poke(&mut self, index: isize, value: u32) -> Result<(), InterpreterError>;
+
+    /// Value of the cell at logical `index` as it should be shown to the
+    /// user, taking signed-cell mode into account. Panics past either end of
+    /// the tape.
+    fn cell_display_value(&self, index: isize) -> i64;
+    fn set_input(&mut self, input: Box<dyn Read>);
+
+    /// Queues bytes for `,`. Queued bytes are read before the input source.
+    fn push_input(&mut self, bytes: &[u8]);
+    fn set_output(&mut self, output: Box<dyn Write>);
+
+    /// Where the `#` debug command writes its tape dumps. Defaults to stderr.
+    fn set_debug_output(&mut self, output: Box<dyn Write>);
+
+    /// Writes one line per executed command to `trace`, such as
+    /// `offset=42 op='+' ptr=3 cell=17`. The writer is flushed when `parse`
+    /// finishes, so wrapping it in a `BufWriter` is cheap.
+    fn set_trace(&mut self, trace: Option<Box<dyn Write>>);
+    fn profile(&self) -> &Profile;
+
+    /// Highest index into `tape` the pointer has reached since the last reset.
+    fn max_tape_index(&self) -> usize;
+
+    /// Index into `tape` of logical cell 0, the cell the pointer started on.
+    /// It is 0 unless a `TapeEnd::GrowBoth` tape grew to the left.
+    fn origin(&self) -> usize;
+    fn set_eof_behavior(&mut self, eof_behavior: EofBehavior);
+    fn set_signed_cells(&mut self, signed: bool);
+
+    /// Makes `,` read a whole cell's worth of little-endian bytes instead of
+    /// a single byte. An incomplete read counts as EOF.
+    fn set_wide_input(&mut self, wide: bool);
+
+    /// Makes `load_file` treat everything after the first `!` in a file as
+    /// the input for `,` instead of as part of the program.
+    fn set_bang_input(&mut self, enabled: bool);
+
+    /// Dry-run mode for measuring programs: `.` writes nothing and `,` reads
+    /// nothing and stores zero, while the profile still counts both.
+    fn set_count_only(&mut self, enabled: bool);
+
+    /// Makes `.` write a newline as `\r\n`, for terminals and files that
+    /// expect Windows line endings. Off by default.
+    fn set_crlf_output(&mut self, enabled: bool);
+
+    /// Numeric I/O: `.` prints the current cell as a decimal number followed
+    /// by a space, and `,` reads a whitespace-delimited decimal number,
+    /// wrapped to the cell range. Off by default.
+    fn set_numeric_io(&mut self, enabled: bool);
+
+    /// Starts or stops recording which cells are written and which are
+    /// printed with `.`, for `write_only_cells`. Off by default, as it costs
+    /// a little on every write.
+    fn set_analyze(&mut self, enabled: bool);
+
+    /// Cells that were changed by the program but never printed since
+    /// analysis was enabled, in tape order. Empty when analysis is off.
+    fn write_only_cells(&self) -> Vec<usize>;
+
+    /// Limits how many commands a run may execute before failing with
+    /// `InterpreterError::StepLimitExceeded`.
+    fn set_max_steps(&mut self, max_steps: Option<u64>);
+
+    /// Limits how long a run may take before failing with
+    /// `InterpreterError::Timeout`. The clock is read only every
+    /// 1024 commands, and a `,` waiting for input is not interrupted, so a
+    /// run can overshoot the timeout slightly.
+    fn set_timeout(&mut self, timeout: Option<Duration>);
+
+    /// Makes execution stop with `InterpreterError::Interrupted` once `flag`
+    /// is set, e.g. from a Ctrl-C handler. The state is left as it was, so
+    /// it can still be inspected.
+    fn set_interrupt_flag(&mut self, flag: Option<Arc<AtomicBool>>);
+    fn set_tape_end(&mut self, tape_end: TapeEnd);
+    fn set_arithmetic_mode(&mut self, arithmetic: ArithmeticMode);
+
+    /// Makes cells hold values below `modulus` instead of the full cell
+    /// width, as some variants wrap at e.g. 100. `.` still prints the value
+    /// modulo 256. Fails unless `modulus` is at least 1 and fits the width.
+    fn set_cell_modulus(&mut self, modulus: Option<u32>) -> Result<(), InterpreterError>;
+
+    /// Enables `#` as a command that dumps the cells around the pointer.
+    fn set_debug_command(&mut self, enabled: bool);
+
+    /// Enables the register of Brainfuck Extended: `@` copies the current
+    /// cell into it and `$` copies it back into the current cell.
+    fn set_extended(&mut self, enabled: bool);
+
+    /// The value last stored with `@`, zero until then.
+    fn register(&self) -> u32;
+
+    /// Enables pbrain's procedures: `:` starts defining the procedure
+    /// numbered by the current cell, the next `:` ends it and `?` calls the
+    /// procedure numbered by the current cell. Definitions do not nest, and
+    /// calls nest up to [`MAX_CALL_DEPTH`].
+    fn set_procedures(&mut self, enabled: bool);
+
+    /// Gives the interpreter `count` empty tapes, each with its own pointer,
+    /// and enables `}` and `{` to switch to the next and previous one. The
+    /// other commands work on the active tape. With a single tape, the
+    /// default, `}` and `{` are comments.
+    fn set_tape_count(&mut self, count: usize);
+
+    /// Index of the tape the other commands work on, 0 on a single tape.
+    fn active_tape(&self) -> usize;
+
+    /// Makes later compiles fail with `InterpreterError::DisallowedByte` at
+    /// the first byte that is neither an enabled command, ASCII whitespace
+    /// nor in `allowed_comments`, for linting generated code. `None`, the
+    /// default, ignores every non-command byte.
+    fn set_strict_ascii(&mut self, allowed_comments: Option<&[u8]>);
+
+    /// Chooses the optimization passes used by later compiles.
+    fn set_opt_level(&mut self, opt_level: OptLevel);
+
+    /// Clears the tape, pointers, counters and loaded program while keeping
+    /// the configuration and I/O handles.
+    fn reset(&mut self);
+
+    /// Translates the loaded program into a standalone C program using the
+    /// configured tape size and cell width.
+    fn transpile_c(&self) -> Result<String, InterpreterError>;
+
+    /// Formats `len` cells starting at logical index `start` as hex and ASCII
+    /// columns, with the current cell wrapped in brackets.
+    fn dump_tape(&self, start: isize, len: usize) -> String;
+
+    /// Number of cells up to and including the last non-zero cell or the
+    /// pointer, whichever is further right.
+    fn used_tape_len(&self) -> usize;
+
+    /// Renders the cells within `radius` of the pointer as a single line,
+    /// highlighting the current cell with ANSI colors when `color` is set.
+    /// With a view offset the window is centred on that cell instead.
+    fn render_tape(&self, radius: usize, color: bool) -> String;
+
+    /// Centres `render_tape` on logical `cell` rather than the pointer, to
+    /// look at distant memory without moving. `None` follows the pointer
+    /// again.
+    fn set_view_offset(&mut self, cell: Option<isize>);
+
+    // Language operations
+    fn increment(&mut self) -> Result<(), InterpreterError>;
+    fn decrement(&mut self) -> Result<(), InterpreterError>;
+    fn add(&mut self, delta: i32) -> Result<(), InterpreterError>;
+    fn set_zero(&mut self);
+    fn store_register(&mut self);
+    fn load_register(&mut self);
+    fn next_tape(&mut self);
+    fn previous_tape(&mut self);
+
+    /// Moves the pointer by `stride` until it reaches a zero cell, honouring
+    /// the tape-end mode like repeated moves would.
+    fn seek_zero(&mut self, stride: i32) -> Result<(), InterpreterError>;
+    fn goto_next_cell(&mut self) -> Result<(), InterpreterError>;
+    fn goto_previous_cell(&mut self) -> Result<(), InterpreterError>;
+    fn move_pointer(&mut self, delta: i32) -> Result<(), InterpreterError>;
+
+    /// Adds `delta` to the cell `offset` cells away and returns to the
+    /// current cell, failing like the equivalent moves and adds would.
+    fn add_at(&mut self, offset: i32, delta: i32) -> Result<(), InterpreterError>;
+    fn open_loop(&mut self, target: usize);
+    fn define_procedure(&mut self, end: usize);
+    fn call_procedure(&mut self) -> Result<(), InterpreterError>;
+    fn return_from_procedure(&mut self);
+    fn close_loop(&mut self, target: usize);
+    fn print(&mut self) -> Result<(), InterpreterError>;
+    fn input(&mut self) -> Result<(), InterpreterError>;
+    fn debug_dump(&mut self) -> Result<(), InterpreterError>;
+}
+
+impl InterpreterState {
+    /// Starts configuring an interpreter option by option.
+    pub fn builder() -> InterpreterBuilder {
+        InterpreterBuilder::new()
+    }
+
+    /// Executes the loaded program lazily, yielding each instruction as it
+    /// runs. Like `step`, this needs `prepare` to have been called.
+    pub fn steps(&mut self) -> Steps<'_> {
+        Steps::new(self)
+    }
+
+    /// Source offset of the instruction currently being executed.
+    fn source_offset(&self) -> usize {
+        self.instructions
+            .get(self.instruction_pointer)
+            .map_or(self.file_content.len(), |instruction| instruction.offset)
+    }
+
+    /// The line, column and included file of source `position`, located the
+    /// same way as bracket errors.
+    fn source_location(&self, position: usize) -> (usize, usize, Option<PathBuf>) {
+        match include::locate(&self.source_map, &self.file_content, position) {
+            Some((path, line, column)) => (line, column, Some(path.to_path_buf())),
+            None => {
+                let (line, column) = line_column(&self.file_content, position);
+                (line, column, None)
+            }
+        }
+    }
+
+    /// The command of the instruction currently being executed, or `fallback`
+    /// when an op runs outside of a program.
+    fn source_command(&self, fallback: char) -> char {
+        self.instructions
+            .get(self.instruction_pointer)
+            .map_or(fallback, |instruction| instruction.op.command())
+    }
+
+    /// Runs the loaded program for [`Interpreter::try_run_string`].
+    pub(crate) fn run_captured(&mut self, input: &[u8]) -> Result<RunResult, InterpreterError> {
+        let captured = CapturedOutput::default();
+        let output = std::mem::replace(&mut self.output, Box::new(captured.clone()));
+        let previous_input = std::mem::replace(&mut self.input, Box::new(io::empty()));
+        let queued = std::mem::replace(&mut self.input_queue, input.iter().copied().collect());
+        let steps_before = self.profile.steps;
+
+        let result = self.parse();
+        self.output = output;
+        self.input = previous_input;
+        self.input_queue = queued;
+        result?;
+
+        let output = captured.0.take();
+        Ok(RunResult {
+            output,
+            tape_snapshot: self.cells[..self.used_tape_len()].to_vec(),
+            pointer: self.pointer(),
+            steps: self.profile.steps - steps_before,
+        })
+    }
+
+    /// Runs the loop of the cat idiom `,[.,]` when its `.` is next, copying
+    /// input to output a block at a time rather than op by op. Steps and op
+    /// counts come out as if the ops had run, including under a step limit,
+    /// but nothing that has to see each op, such as a trace or numeric I/O,
+    /// may be enabled. Returns whether it ran. When the input ends with an
+    /// EOF value that isn't zero, it stops on the `.` so the loop goes on.
+    fn copy_input(&mut self) -> Result<bool, InterpreterError> {
+        let at = self.instruction_pointer;
+        let ops = at.checked_sub(1).and_then(|start| self.instructions.get(start..at + 3));
+        let Some(&[start, print, read, end]) = ops else {
+            return Ok(false);
+        };
+        if !matches!(
+            (start.op, print.op, read.op, end.op),
+            (Op::LoopStart(_), Op::Print, Op::Input, Op::LoopEnd(_))
+        ) {
+            return Ok(false);
+        }
+        let observed = self.trace.is_some()
+            || !self.watchpoints.is_empty()
+            || !self.breakpoints.is_empty()
+            || self.history_limit > 0;
+        let plain_io = !(self.numeric_io || self.crlf_output || self.wide_input || self.count_only)
+            && self.cell_usage.is_none()
+            && self.cell_modulus.is_none();
+        // Every pass through the loop takes the steps of `.`, `,` and `]`.
+        let per_pass = (print.commands + read.commands + end.commands) as u64;
+        let mut passes = self.max_steps.map_or(u64::MAX, |limit| {
+            limit.saturating_sub(self.profile.steps) / per_pass
+        });
+        if observed || !plain_io || passes == 0 {
+            return Ok(false);
+        }
+
+        let mut block = vec![0; COPY_BLOCK_SIZE];
+        loop {
+            self.check_interrupt()?;
+            if let Some(timeout) = self.timeout {
+                if self.started_at.elapsed() >= timeout {
+                    return Err(InterpreterError::Timeout { timeout });
+                }
+            }
+            self.output.flush()?;
+            let mut len = 0;
+            while len < block.len() {
+                let Some(byte) = self.input_queue.pop_front() else { break };
+                block[len] = byte;
+                len += 1;
+            }
+            if len == 0 {
+                len = self.input.read(&mut block)?;
+            }
+
+            // A pass prints the cell and reads the next byte into it, which
+            // the next pass prints, until a zero byte ends the loop.
+            self.output.write_all(&[self.cells[self.cell_index] as u8])?;
+            let read = match block[..len].iter().position(|&byte| byte == 0) {
+                Some(zero) => zero + 1,
+                None => len,
+            };
+            let read = read.min(passes.min(usize::MAX as u64) as usize);
+            if read == 0 {
+                self.store_eof();
+            } else {
+                self.output.write_all(&block[..read - 1])?;
+                self.cells[self.cell_index] = block[read - 1] as u32;
+                let unread = block[read..len].iter().rev();
+                unread.for_each(|&byte| self.input_queue.push_front(byte));
+            }
+
+            let passed = read.max(1) as u64;
+            let cell_is_zero = self.cells[self.cell_index] == 0;
+            self.profile.outputs += passed;
+            self.profile.inputs += passed;
+            self.profile.steps += per_pass * passed;
+            self.profile.loop_jumps += passed - cell_is_zero as u64;
+            passes -= passed;
+            if cell_is_zero {
+                self.instruction_pointer = at + 3;
+                return Ok(true);
+            }
+            if read == 0 || passes == 0 {
+                return Ok(true);
+            }
+        }
+    }
+
+    /// Parks the active tape with its pointer and brings in tape `tape`.
+    fn switch_tape(&mut self, tape: usize) {
+        if tape == self.active_tape {
+            return;
+        }
+        let parked = &mut self.tapes[self.active_tape];
+        std::mem::swap(&mut parked.cells, &mut self.cells);
+        parked.cell_index = self.cell_index;
+        parked.origin = self.origin;
+
+        let active = &mut self.tapes[tape];
+        std::mem::swap(&mut active.cells, &mut self.cells);
+        self.cell_index = active.cell_index;
+        self.origin = active.origin;
+        self.active_tape = tape;
+    }
+
+    /// Stores what `,` yields once the input is exhausted.
+    fn store_eof(&mut self) {
+        match self.eof_behavior {
+            EofBehavior::Zero => self.cells[self.cell_index] = 0,
+            EofBehavior::MinusOne => self.cells[self.cell_index] = self.cell_max(),
+            EofBehavior::Unchanged => {}
+        }
+    }
+
+    fn read_input_byte(&mut self) -> Result<Option<u8>, InterpreterError> {
+        if let Some(byte) = self.input_queue.pop_front() {
+            return Ok(Some(byte));
+        }
+        let mut byte = [0];
+        Ok(match self.input.read(&mut byte)? {
+            0 => None,
+            _ => Some(byte[0]),
+        })
+    }
+
+    /// Reads the next whitespace-delimited number for numeric I/O, wrapped
+    /// to the cell range. `None` means the input ended before a number.
+    fn read_number(&mut self) -> Result<Option<u32>, InterpreterError> {
+        let mut input = String::new();
+        while let Some(byte) = self.read_input_byte()? {
+            if !byte.is_ascii_whitespace() {
+                input.push(byte as char);
+            } else if !input.is_empty() {
+                break;
+            }
+        }
+        if input.is_empty() {
+            return Ok(None);
+        }
+        let value: i64 = input.parse().map_err(|_| InterpreterError::InvalidNumber { input })?;
+        Ok(Some(value.rem_euclid(self.cell_max() as i64 + 1) as u32))
+    }
+
+    /// Appends a program file, see [`Interpreter::load_file`].
+    fn load_path(&mut self, path: &Path) -> Result<(), InterpreterError> {
+        if !path.exists() {
+            return Err(InterpreterError::FileNotFound(path.to_path_buf()));
+        }
+        let mut contents = Vec::new();
+        File::open(path)?.read_to_end(&mut contents)?;
+
+        if contents.starts_with(&GZIP_MAGIC) {
+            let mut decompressed = Vec::new();
+            GzDecoder::new(contents.as_slice()).read_to_end(&mut decompressed)?;
+            contents = decompressed;
+        }
+        let program = blank_shebang(contents);
+        let bang = program.iter().position(|&byte| byte == b'!');
+        let program = match bang {
+            Some(bang) if self.bang_input => {
+                self.input = Box::new(Cursor::new(program[bang + 1..].to_vec()));
+                &program[..bang]
+            }
+            _ => &program,
+        };
+        let spans = include::expand(path, program, &mut self.file_content)?;
+        self.source_map.extend(spans);
+        Ok(())
+    }
+
+    /// Points a bracket error at the included file it came from, if any.
+    fn locate_error(&self, err: InterpreterError) -> InterpreterError {
+        let (InterpreterError::UnmatchedOpen { position, .. }
+        | InterpreterError::UnmatchedClose { position, .. }
+        | InterpreterError::UnmatchedProcedure { position, .. }
+        | InterpreterError::DisallowedByte { position, .. }) = &err
+        else {
+            return err;
+        };
+        let position = *position;
+        let Some((path, line, column)) =
+            include::locate(&self.source_map, &self.file_content, position)
+        else {
+            return err;
+        };
+        let file = Some(path.to_path_buf());
+        match err {
+            InterpreterError::UnmatchedOpen { .. } => {
+                InterpreterError::UnmatchedOpen { position, line, column, file }
+            }
+            InterpreterError::UnmatchedProcedure { command, .. } => {
+                InterpreterError::UnmatchedProcedure { position, line, column, file, command }
+            }
+            InterpreterError::DisallowedByte { byte, .. } => {
+                InterpreterError::DisallowedByte { position, line, column, file, byte }
+            }
+            _ => InterpreterError::UnmatchedClose { position, line, column, file },
+        }
+    }
+
+    /// Inserts at least `count` zero cells on the left of the tape and
+    /// returns how many. The tape stays a `Vec` so that `tape` can return a
+    /// slice, so it at least doubles each time to keep the shifts this costs
+    /// linear overall. Physical indices move along; logical ones stay put.
+    fn prepend_cells(&mut self, count: usize) -> usize {
+        let added = count.max(self.cells.len());
+        self.cells.splice(0..0, std::iter::repeat_n(0, added));
+        self.cell_index += added;
+        self.origin += added;
+        self.profile.max_cell_index += added;
+        // Keep the recorded pointers and cells on the cells they were at.
+        let active_tape = self.active_tape;
+        for undo in self.history.iter_mut().filter(|undo| undo.tape == active_tape) {
+            undo.pointer += added;
+            if let Some((cell, _)) = &mut undo.cell {
+                *cell += added;
+            }
+        }
+        if let Some(usage) = &mut self.cell_usage {
+            usage.splice(0..0, std::iter::repeat_n(CellUsage::default(), added));
+        }
+        added
+    }
+
+    /// The logical index of the cell at `cell` in `cells`.
+    fn logical(&self, cell: usize) -> isize {
+        cell as isize - self.origin as isize
+    }
+
+    /// Where the cell at logical `index` is in `cells`, or `None` off the tape.
+    fn physical(&self, index: isize) -> Option<usize> {
+        let cell = usize::try_from(index.checked_add(self.origin as isize)?).ok()?;
+        (cell < self.cells.len()).then_some(cell)
+    }
+
+    /// Like `cell_display_value`, for the cell at `cell` in `cells`.
+    fn display_value(&self, cell: usize) -> i64 {
+        let value = self.cells[cell];
+        if self.signed_cells {
+            self.cell_width.to_signed(value)
+        } else {
+            value as i64
+        }
+    }
+
+    /// Records what happened to the current cell when analysis is on.
+    fn record_usage(&mut self, record: impl FnOnce(&mut CellUsage)) {
+        if let Some(usage) = &mut self.cell_usage {
+            if usage.len() <= self.cell_index {
+                usage.resize(self.cell_index + 1, CellUsage::default());
+            }
+            record(&mut usage[self.cell_index]);
+        }
+    }
+
+    /// How many passes a `[-]` or `[+]` loop, adding `delta` each, takes to
+    /// clear the current cell.
+    fn clear_passes(&self, delta: i32) -> u64 {
+        let cell = self.cells[self.cell_index] as u64;
+        match delta {
+            _ if cell == 0 => 0,
+            delta if delta < 0 => cell,
+            _ => self.cell_max() as u64 + 1 - cell,
+        }
+    }
+
+    /// Counts the jumps of a replaced loop that made `passes` passes: its `[`
+    /// jumps past it when it makes none, and otherwise every `]` but the last
+    /// jumps back.
+    fn count_replaced_jumps(&mut self, passes: u64) {
+        self.profile.loop_jumps += if passes == 0 { 1 } else { passes - 1 };
+    }
+
+    /// The largest value a cell can hold.
+    fn cell_max(&self) -> u32 {
+        self.cell_modulus.map_or(self.cell_width.max_value(), |modulus| modulus - 1)
+    }
+
+    /// The cell a move by `delta` would land on, before tape-end errors or
+    /// growth are taken into account.
+    fn move_target(&self, delta: i32) -> Option<usize> {
+        let target = self.cell_index as i64 + delta as i64;
+        let wrap = self.pointer_wrap.unwrap_or(self.cells.len()) as i64;
+        let target = match self.tape_end {
+            TapeEnd::Wrap => target.rem_euclid(wrap),
+            TapeEnd::Grow | TapeEnd::Error | TapeEnd::GrowBoth => target,
+        };
+        usize::try_from(target).ok()
+    }
+
+    /// Stands in for a loop that can never end, so that the step limit and
+    /// timeout still stop it.
+    fn spin_forever(&self) -> Result<(), InterpreterError> {
+        if let Some(limit) = self.max_steps {
+            return Err(InterpreterError::StepLimitExceeded { limit });
+        }
+        loop {
+            self.check_interrupt()?;
+            let remaining = self.timeout.map(|timeout| {
+                (timeout, timeout.saturating_sub(self.started_at.elapsed()))
+            });
+            if let Some((timeout, Duration::ZERO)) = remaining {
+                return Err(InterpreterError::Timeout { timeout });
+            }
+            let poll = self.interrupt.as_ref().map(|_| INTERRUPT_POLL_INTERVAL);
+            match remaining.map(|(_, remaining)| remaining).into_iter().chain(poll).min() {
+                Some(wait) => std::thread::park_timeout(wait),
+                None => std::thread::park(),
+            }
+        }
+    }
+
+    fn check_interrupt(&self) -> Result<(), InterpreterError> {
+        match &self.interrupt {
+            Some(flag) if flag.load(Ordering::Relaxed) => {
+                Err(InterpreterError::Interrupted { steps: self.profile.steps })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Runs the compiled program to the end, then flushes the output and the
+    /// trace whether or not it succeeded.
+    fn execute(&mut self) -> Result<(), InterpreterError> {
+        self.execute_until(false).map(|_| ())
+    }
+
+    /// Like `execute`, but with `breakpoints` set it stops before an op
+    /// starting at a breakpoint. The op it starts at always runs, so
+    /// calling it again moves past the breakpoint it paused at.
+    fn execute_until(&mut self, breakpoints: bool) -> Result<RunStatus, InterpreterError> {
+        let result = self.run_to_end(breakpoints);
+        self.output.flush()?;
+        if let Some(trace) = &mut self.trace {
+            trace.flush()?;
+        }
+        result
+    }
+
+    fn run_to_end(&mut self, breakpoints: bool) -> Result<RunStatus, InterpreterError> {
+        let start = self.instruction_pointer;
+        while let Some(instruction) = self.instructions.get(self.instruction_pointer) {
+            let offset = instruction.offset;
+            if breakpoints && self.instruction_pointer != start && self.breakpoints.contains(&offset)
+            {
+                return Ok(RunStatus::Paused { offset });
+            }
+            self.step()?;
+            if let Some(hit) = self.watch_hit.take() {
+                writeln!(self.debug_output, "{hit}")?;
+            }
+        }
+        Ok(RunStatus::Finished)
+    }
+}
+
+
+impl Interpreter for InterpreterState {
+    fn new() -> Self {
+        Self::with_tape_size(DEFAULT_TAPE_SIZE)
+    }
+
+    fn with_tape_size(tape_size: usize) -> Self {
+        Self::with_config(tape_size, CellWidth::Eight)
+    }
+
+    fn with_config(tape_size: usize, cell_width: CellWidth) -> Self {
+        Self {
+            cell_index: 0,
+            origin: 0,
+            tapes: Vec::new(),
+            active_tape: 0,
+            view_offset: None,
+            cells: vec![0; tape_size.max(1)],
+            cell_width,
+            eof_behavior: EofBehavior::Zero,
+            signed_cells: false,
+            wide_input: false,
+            bang_input: false,
+            count_only: false,
+            crlf_output: false,
+            numeric_io: false,
+            cell_usage: None,
+            max_steps: None,
+            timeout: None,
+            interrupt: None,
+            started_at: Instant::now(),
+            tape_end: TapeEnd::Wrap,
+            pointer_wrap: None,
+            arithmetic: ArithmeticMode::Wrap,
+            cell_modulus: None,
+            register: 0,
+            procedures: HashMap::new(),
+            call_stack: Vec::new(),
+            compile_options: CompileOptions::default(),
+            file_content: Vec::new(),
+            source_map: Vec::new(),
+            instructions: Vec::new(),
+            instruction_pointer: 0,
+            watchpoints: Vec::new(),
+            watch_hit: None,
+            history: VecDeque::new(),
+            history_limit: 0,
+            breakpoints: Vec::new(),
+            pending_line: String::new(),
+            input: Box::new(stdin()),
+            input_queue: VecDeque::new(),
+            output: Box::new(stdout()),
+            debug_output: Box::new(stderr()),
+            trace: None,
+            profile: Profile::default(),
+        }
+    }
+
+    fn with_pointer_wrap(
+        tape_size: usize,
+        cell_width: CellWidth,
+        pointer_wrap: Option<usize>,
+    ) -> Result<Self, InterpreterError> {
+        if let Some(wrap) = pointer_wrap {
+            if wrap == 0 || wrap > tape_size {
+                return Err(InterpreterError::InvalidPointerWrap { wrap, tape_size });
+            }
+        }
+        let mut interpreter = Self::with_config(tape_size, cell_width);
+        interpreter.pointer_wrap = pointer_wrap;
+        Ok(interpreter)
+    }
+
+    fn load_file(&mut self, input_filename: &str) -> Result<(), InterpreterError> {
+        self.load_path(Path::new(input_filename))
+    }
+
+    fn load(&mut self, source: ProgramSource) -> Result<(), InterpreterError> {
+        match source {
+            ProgramSource::File(path) => self.load_path(&path),
+            ProgramSource::Stdin => self.load_stdin(),
+            ProgramSource::Bytes(bytes) => {
+                self.file_content.extend_from_slice(&bytes);
+                Ok(())
+            }
+        }
+    }
+
+    fn load_string(&mut self, program: &str) {
+        self.load_bytes(program.as_bytes());
+    }
+
+    fn run_file_mapped(&mut self, filename: &str) -> Result<(), InterpreterError> {
+        let path = Path::new(filename);
+        if !path.exists() {
+            return Err(InterpreterError::FileNotFound(path.to_path_buf()));
+        }
+        let file = File::open(path)?;
+        // SAFETY: the map is only read while compiling below. Like any mapped
+        // file, it must not be truncated by another process in the meantime.
+        let map = unsafe { Mmap::map(&file)? };
+
+        self.file_content.clear();
+        self.source_map.clear();
+        self.instructions = compile_instructions(&map[..], &self.compile_options)?;
+        drop(map);
+        self.instruction_pointer = 0;
+        self.started_at = Instant::now();
+        self.execute()
+    }
+
+    fn load_bytes(&mut self, program: &[u8]) {
+        self.file_content = program.to_vec();
+        self.source_map.clear();
+    }
+
+    fn load_stdin(&mut self) -> Result<(), InterpreterError> {
+        stdin().read_to_end(&mut self.file_content)?;
+        Ok(())
+    }
+
+    fn load_until_sentinel(&mut self, mut source: Box<dyn BufRead>) -> Result<(), InterpreterError> {
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            if source.read_until(b'\n', &mut line)? == 0
+                || line.trim_ascii_end() == PROGRAM_SENTINEL.as_bytes()
+            {
+                break;
+            }
+            self.file_content.extend_from_slice(&line);
+        }
+        self.input = source;
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), InterpreterError> {
+        compile::check_brackets(&self.file_content).map_err(|err| self.locate_error(err))
+    }
+
+    fn prepare(&mut self) -> Result<(), InterpreterError> {
+        self.instructions = compile_instructions(&self.file_content, &self.compile_options)
+            .map_err(|err| self.locate_error(err))?;
+        self.instruction_pointer = 0;
+        self.history.clear();
+        self.procedures.clear();
+        self.call_stack.clear();
+        self.started_at = Instant::now();
+        Ok(())
+    }
+
+    fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    fn source_map(&self) -> Value {
+        // Offsets only grow along the ops, so lines and columns are counted
+        // in a single pass. Columns count characters like `line_column`.
+        let (mut scanned, mut line, mut column) = (0, 1, 1);
+        let entries = self.instructions.iter().enumerate().map(|(index, instruction)| {
+            for &byte in &self.file_content[scanned..instruction.offset] {
+                if byte == b'\n' {
+                    (line, column) = (line + 1, 1);
+                } else if byte & 0xc0 != 0x80 {
+                    column += 1;
+                }
+            }
+            scanned = instruction.offset;
+
+            let offset = instruction.offset;
+            match include::locate(&self.source_map, &self.file_content, offset) {
+                Some((file, line, column)) => json!({
+                    "op": index,
+                    "offset": offset,
+                    "line": line,
+                    "column": column,
+                    "file": file,
+                }),
+                None => json!({ "op": index, "offset": offset, "line": line, "column": column }),
+            }
+        });
+        Value::Array(entries.collect())
+    }
+
+    fn parse(&mut self) -> Result<(), InterpreterError> {
+        self.prepare()?;
+        self.execute()
+    }
+
+    fn step(&mut self) -> Result<Option<char>, InterpreterError> {
+        let Some(instruction) = self.instructions.get(self.instruction_pointer).copied() else {
+            return Ok(None);
+        };
+
+        let commands = instruction.commands as u64;
+        if let Some(limit) = self.max_steps {
+            if self.profile.steps + commands > limit {
+                return Err(InterpreterError::StepLimitExceeded { limit });
+            }
+        }
+        self.check_interrupt()?;
+        if let Some(timeout) = self.timeout {
+            let next_check = self.profile.steps.next_multiple_of(TIMEOUT_CHECK_INTERVAL);
+            let check = next_check < self.profile.steps + commands;
+            if check && self.started_at.elapsed() >= timeout {
+                return Err(InterpreterError::Timeout { timeout });
+            }
+        }
+        if instruction.op == Op::Print && self.copy_input()? {
+            return Ok(Some('.'));
+        }
+
+        // Apart from `AddAt`, ops only ever write the current cell, so a
+        // watched cell can only change when the pointer starts on it.
+        let written = match instruction.op {
+            Op::AddAt(offset, _) => self.move_target(offset),
+            Op::NextTape | Op::PreviousTape => None,
+            _ => Some(self.cell_index),
+        };
+        let watched = written.map(|cell| self.logical(cell));
+        let watched = watched.filter(|cell| self.watchpoints.contains(cell));
+        let old = watched.and_then(|cell| self.peek(cell)).unwrap_or(0);
+        if self.history_limit > 0 {
+            if self.history.len() == self.history_limit {
+                self.history.pop_front();
+            }
+            self.history.push_back(Undo {
+                tape: self.active_tape,
+                pointer: self.cell_index,
+                cell: written.and_then(|cell| Some((cell, *self.cells.get(cell)?))),
+                register: self.register,
+                instruction_pointer: self.instruction_pointer,
+                steps: self.profile.steps,
+                call_depth: self.call_stack.len(),
+                call_top: self.call_stack.last().copied(),
+                definition: matches!(instruction.op, Op::ProcedureStart(_)).then(|| {
+                    let id = self.cells[self.cell_index];
+                    (id, self.procedures.get(&id).copied())
+                }),
+            });
+        }
+
+        // Clears and scans take the steps of every pass their loop would
+        // have made, the body's commands and the `]`, and count its body's
+        // commands and its jumps.
+        let moves = self.profile.moves_right + self.profile.moves_left;
+        let clear_passes = match instruction.op {
+            Op::SetZero(delta) => self.clear_passes(delta),
+            _ => 0,
+        };
+        match instruction.op {
+            Op::Inc => self.increment()?,
+            Op::Dec => self.decrement()?,
+            Op::Next => self.goto_next_cell()?,
+            Op::Prev => self.goto_previous_cell()?,
+            Op::Print => self.print()?,
+            Op::Input => self.input()?,
+            Op::Add(delta) => self.add(delta)?,
+            Op::Move(delta) => self.move_pointer(delta)?,
+            Op::AddAt(offset, delta) => self.add_at(offset, delta)?,
+            Op::SetZero(_) => self.set_zero(),
+            Op::SeekZero(stride) => self.seek_zero(stride)?,
+            Op::DebugDump => self.debug_dump()?,
+            Op::StoreRegister => self.store_register(),
+            Op::LoadRegister => self.load_register(),
+            Op::ProcedureStart(end) => self.define_procedure(end),
+            Op::ProcedureEnd(_) => self.return_from_procedure(),
+            Op::Call => self.call_procedure()?,
+            Op::NextTape => self.next_tape(),
+            Op::PreviousTape => self.previous_tape(),
+            Op::LoopStart(target) => self.open_loop(target),
+            Op::LoopEnd(target) => self.close_loop(target),
+        }
+        self.instruction_pointer += 1;
+        let pass_steps = match instruction.op {
+            Op::SetZero(delta) => {
+                if delta < 0 {
+                    self.profile.decrements += clear_passes;
+                } else {
+                    self.profile.increments += clear_passes;
+                }
+                self.count_replaced_jumps(clear_passes);
+                2 * clear_passes
+            }
+            Op::SeekZero(stride) => {
+                let stride = stride.unsigned_abs() as u64;
+                let moved = self.profile.moves_right + self.profile.moves_left - moves;
+                self.count_replaced_jumps(moved / stride);
+                moved / stride * (stride + 1)
+            }
+            _ => 0,
+        };
+        self.profile.steps += commands + pass_steps;
+        if let Some(limit) = self.max_steps {
+            if self.profile.steps > limit {
+                return Err(InterpreterError::StepLimitExceeded { limit });
+            }
+        }
+
+        if let Some(cell) = watched {
+            let new = self.peek(cell).unwrap_or(0);
+            if new != old {
+                self.watch_hit = Some(WatchHit { cell, old, new, offset: instruction.offset });
+            }
+        }
+
+        let command = instruction.op.command();
+        if let Some(trace) = &mut self.trace {
+            writeln!(
+                trace,
+                "offset={} op='{command}' ptr={} cell={}",
+                instruction.offset,
+                self.cell_index,
+                self.cells[self.cell_index]
+            )?;
+        }
+        Ok(Some(command))
+    }
+
+    fn add_watchpoint(&mut self, cell: isize) {
+        if !self.watchpoints.contains(&cell) {
+            self.watchpoints.push(cell);
+        }
+    }
+
+    fn take_watch_hit(&mut self) -> Option<WatchHit> {
+        self.watch_hit.take()
+    }
+
+    fn export_state(&self) -> String {
+        let other_tapes: Vec<Value> = (0..self.tapes.len())
+            .filter(|&index| index != self.active_tape)
+            .map(|index| {
+                let tape = &self.tapes[index];
+                json!({
+                    "tape_size": tape.cells.len(),
+                    "tape": &tape.cells[..used_len(&tape.cells, tape.cell_index)],
+                    "pointer": tape.cell_index,
+                    "origin": tape.origin,
+                })
+            })
+            .collect();
+        let mut procedures: Vec<(u32, usize)> =
+            self.procedures.iter().map(|(&id, &start)| (id, start)).collect();
+        procedures.sort_unstable();
+        json!({
+            "tape_size": self.cells.len(),
+            "tape": &self.cells[..self.used_tape_len()],
+            "pointer": self.cell_index,
+            "origin": self.origin,
+            "active_tape": self.active_tape,
+            "other_tapes": other_tapes,
+            "register": self.register,
+            "procedures": procedures,
+            "call_stack": self.call_stack,
+            "instruction_pointer": self.instruction_pointer,
+            "steps": self.profile.steps,
+        })
+        .to_string()
+    }
+
+    fn import_state(&mut self, json: &str) -> Result<(), InterpreterError> {
+        let invalid = |reason: String| InterpreterError::InvalidState { reason };
+        let state: Value = serde_json::from_str(json).map_err(|err| invalid(err.to_string()))?;
+        let number = |object: &Value, field: &str| {
+            object
+                .get(field)
+                .and_then(Value::as_u64)
+                .ok_or_else(|| invalid(format!("\"{field}\" must be a number")))
+        };
+        let list = |field: &str| {
+            state
+                .get(field)
+                .and_then(Value::as_array)
+                .ok_or_else(|| invalid(format!("\"{field}\" must be a list")))
+        };
+        let max = self.cell_max() as u64;
+        let max_tape_size = self.cells.len().max(MAX_IMPORTED_TAPE_SIZE);
+        let parse_tape = |object: &Value| {
+            let tape_size = number(object, "tape_size")? as usize;
+            let pointer = number(object, "pointer")? as usize;
+            let origin = number(object, "origin")? as usize;
+            let cells = object.get("tape").and_then(Value::as_array).and_then(|tape| {
+                tape.iter()
+                    .map(|cell| cell.as_u64().filter(|&cell| cell <= max).map(|cell| cell as u32))
+                    .collect::<Option<Vec<u32>>>()
+            });
+            let Some(mut cells) = cells else {
+                return Err(invalid(format!("\"tape\" must be a list of cells up to {max}")));
+            };
+            if tape_size > max_tape_size {
+                return Err(invalid(format!("the tape size {tape_size} is too large")));
+            }
+            if cells.len() > tape_size || pointer >= tape_size || origin >= tape_size {
+                let reason = "the tape, pointer or origin exceed the tape size";
+                return Err(invalid(String::from(reason)));
+            }
+            cells.resize(tape_size, 0);
+            Ok(Tape { cells, cell_index: pointer, origin })
+        };
+        let is_op = |index: usize, expected: fn(Op) -> bool| {
+            self.instructions.get(index).is_some_and(|instruction| expected(instruction.op))
+        };
+
+        let active = parse_tape(&state)?;
+        let active_tape = number(&state, "active_tape")? as usize;
+        let other_tapes: Vec<Tape> =
+            list("other_tapes")?.iter().map(parse_tape).collect::<Result<_, _>>()?;
+        let tape_count = self.tapes.len().max(1);
+        if active_tape >= tape_count || other_tapes.len() + 1 != tape_count {
+            return Err(invalid(format!("the interpreter has {tape_count} tapes")));
+        }
+        let register = number(&state, "register")?;
+        if register > max {
+            return Err(invalid(format!("the register must be at most {max}")));
+        }
+        let mut procedures = HashMap::new();
+        for procedure in list("procedures")? {
+            let definition = procedure.as_array().and_then(|pair| match pair.as_slice() {
+                [id, start] => Some((u32::try_from(id.as_u64()?).ok()?, start.as_u64()? as usize)),
+                _ => None,
+            });
+            match definition {
+                Some((id, start)) if is_op(start, |op| matches!(op, Op::ProcedureStart(_))) => {
+                    procedures.insert(id, start);
+                }
+                _ => return Err(invalid(String::from("a procedure does not start at a ':'"))),
+            }
+        }
+        let call_stack: Option<Vec<usize>> = list("call_stack")?
+            .iter()
+            .map(|call| {
+                let call = call.as_u64()? as usize;
+                is_op(call, |op| op == Op::Call).then_some(call)
+            })
+            .collect();
+        let call_stack = call_stack.filter(|calls| calls.len() <= MAX_CALL_DEPTH);
+        let Some(call_stack) = call_stack else {
+            return Err(invalid(String::from("a running call is not at a '?'")));
+        };
+        let instruction_pointer = number(&state, "instruction_pointer")? as usize;
+        if instruction_pointer > self.instructions.len() {
+            return Err(invalid(String::from("the position is past the end of the program")));
+        }
+        let steps = number(&state, "steps")?;
+
+        let parked = (0..self.tapes.len()).filter(|&index| index != active_tape);
+        for (index, tape) in parked.zip(other_tapes) {
+            self.tapes[index] = tape;
+        }
+        self.active_tape = active_tape;
+        self.cells = active.cells;
+        self.cell_index = active.cell_index;
+        self.origin = active.origin;
+        self.register = register as u32;
+        self.procedures = procedures;
+        self.call_stack = call_stack;
+        self.instruction_pointer = instruction_pointer;
+        self.profile.steps = steps;
+        self.history.clear();
+        Ok(())
+    }
+
+    fn add_breakpoint(&mut self, offset: usize) {
+        if !self.breakpoints.contains(&offset) {
+            self.breakpoints.push(offset);
+        }
+    }
+
+    fn resume(&mut self) -> Result<RunStatus, InterpreterError> {
+        self.execute_until(true)
+    }
+
+    fn set_history_limit(&mut self, limit: usize) {
+        self.history_limit = limit;
+        while self.history.len() > limit {
+            self.history.pop_front();
+        }
+    }
+
+    fn step_back(&mut self) -> bool {
+        let Some(undo) = self.history.pop_back() else {
+            return false;
+        };
+        self.switch_tape(undo.tape);
+        if let Some((cell, old)) = undo.cell {
+            self.cells[cell] = old;
+        }
+        self.cell_index = undo.pointer;
+        self.register = undo.register;
+        self.instruction_pointer = undo.instruction_pointer;
+        self.profile.steps = undo.steps;
+        self.call_stack.truncate(undo.call_depth);
+        if let Some(top) = undo.call_top.filter(|_| self.call_stack.len() < undo.call_depth) {
+            self.call_stack.push(top);
+        }
+        match undo.definition {
+            Some((id, Some(start))) => _ = self.procedures.insert(id, start),
+            Some((id, None)) => _ = self.procedures.remove(&id),
+            None => {}
+        }
+        true
+    }
+
+    fn step_over(&mut self) -> Result<Option<char>, InterpreterError> {
+        let Some(Instruction { op: Op::LoopStart(end), .. }) =
+            self.instructions.get(self.instruction_pointer).copied()
+        else {
+            return self.step();
+        };
+
+        let command = self.step()?;
+        while self.instruction_pointer != end + 1 && self.step()?.is_some() {}
+        Ok(command)
+    }
+
+    fn run_string(&mut self, program: &str) -> Result<(), InterpreterError> {
+        self.run_bytes(program.as_bytes())
+    }
+
+    fn run_bytes(&mut self, program: &[u8]) -> Result<(), InterpreterError> {
+        self.load_bytes(program);
+        self.parse()
+    }
+
+    fn try_run_string(
+        &mut self,
+        program: &str,
+        input: &[u8],
+    ) -> Result<RunResult, InterpreterError> {
+        self.load_string(program);
+        self.run_captured(input)
+    }
+
+
+    fn eval_line(&mut self, line: &str) -> Result<(), InterpreterError> {
+        self.pending_line.push_str(line);
+
+        let depth = self.pending_line.bytes().fold(0isize, |depth, byte| match byte {
+            b'[' => depth + 1,
+            b']' => depth - 1,
+            _ => depth,
+        });
+        if depth > 0 {
+            return Ok(());
+        }
+
+        let snippet = std::mem::take(&mut self.pending_line);
+        self.run_string(&snippet)
+    }
+
+    fn is_line_pending(&self) -> bool {
+        !self.pending_line.is_empty()
+    }
+
+    fn pointer(&self) -> isize {
+        self.logical(self.cell_index)
+    }
+
+    fn current_cell(&self) -> u32 {
+        self.cells[self.cell_index]
+    }
+
+    fn tape(&self) -> &[u32] {
+        &self.cells
+    }
+
+    fn peek(&self, index: isize) -> Option<u32> {
+        Some(self.cells[self.physical(index)?])
+    }
+
+    fn poke(&mut self, index: isize, value: u32) -> Result<(), InterpreterError> {
+        let tape_size = self.cells.len();
+        let max = self.cell_max();
+        let cell =
+            self.physical(index).ok_or(InterpreterError::CellOutOfBounds { index, tape_size })?;
+        if value > max {
+            return Err(InterpreterError::CellValueOutOfRange { value, max });
+        }
+        self.cells[cell] = value;
+        Ok(())
+    }
+
+    fn cell_display_value(&self, index: isize) -> i64 {
+        let cell = self.physical(index).expect("the cell is on the tape");
+        self.display_value(cell)
+    }
+
+    fn set_input(&mut self, input: Box<dyn Read>) {
+        self.input = input;
+    }
+
+    fn push_input(&mut self, bytes: &[u8]) {
+        self.input_queue.extend(bytes);
+    }
+
+    fn set_output(&mut self, output: Box<dyn Write>) {
+        self.output = output;
+    }
+
+    fn set_debug_output(&mut self, output: Box<dyn Write>) {
+        self.debug_output = output;
+    }
+
+    fn set_trace(&mut self, trace: Option<Box<dyn Write>>) {
+        self.trace = trace;
+    }
+
+    fn profile(&self) -> &Profile {
+        &self.profile
+    }
+
+    fn max_tape_index(&self) -> usize {
+        self.profile.max_cell_index
+    }
+
+    fn origin(&self) -> usize {
+        self.origin
+    }
+
+    fn dump_tape(&self, start: isize, len: usize) -> String {
+        // Cells left of the tape are skipped, like those right of it.
+        let skipped = self.origin as isize + start;
+        let start = skipped.max(0) as usize;
+        let len = len.saturating_sub(skipped.min(0).unsigned_abs());
+        let (width, origin) = (self.cell_width.hex_digits(), self.origin);
+        dump::dump(&self.cells, width, start, len, self.cell_index, origin)
+    }
+
+    fn used_tape_len(&self) -> usize {
+        used_len(&self.cells, self.cell_index)
+    }
+
+    fn render_tape(&self, radius: usize, color: bool) -> String {
+        let last = self.cells.len() - 1;
+        let view = self.view_offset.map(|cell| (self.origin as isize + cell).max(0) as usize);
+        let centre = view.map_or(self.cell_index, |cell| cell.min(last));
+        let start = centre.saturating_sub(radius);
+        let end = centre.saturating_add(radius + 1).min(self.cells.len());
+        let values: Vec<i64> = (start..end).map(|cell| self.display_value(cell)).collect();
+        visualize::render(&values, self.logical(start), self.pointer(), color)
+    }
+
+    fn set_view_offset(&mut self, cell: Option<isize>) {
+        self.view_offset = cell;
+    }
+
+    fn set_eof_behavior(&mut self, eof_behavior: EofBehavior) {
+        self.eof_behavior = eof_behavior;
+    }
+
+    fn set_signed_cells(&mut self, signed: bool) {
+        self.signed_cells = signed;
+    }
+
+    fn set_wide_input(&mut self, wide: bool) {
+        self.wide_input = wide;
+    }
+
+    fn set_bang_input(&mut self, enabled: bool) {
+        self.bang_input = enabled;
+    }
+
+    fn set_count_only(&mut self, enabled: bool) {
+        self.count_only = enabled;
+    }
+
+    fn set_crlf_output(&mut self, enabled: bool) {
+        self.crlf_output = enabled;
+    }
+
+    fn set_numeric_io(&mut self, enabled: bool) {
+        self.numeric_io = enabled;
+    }
+
+    fn set_analyze(&mut self, enabled: bool) {
+        self.cell_usage = enabled.then(Vec::new);
+    }
+
+    fn write_only_cells(&self) -> Vec<usize> {
+        let Some(usage) = &self.cell_usage else {
+            return Vec::new();
+        };
+        usage
+            .iter()
+            .enumerate()
+            .filter(|(_, usage)| usage.written && !usage.printed)
+            .map(|(cell, _)| cell)
+            .collect()
+    }
+
+    fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    fn set_interrupt_flag(&mut self, flag: Option<Arc<AtomicBool>>) {
+        self.interrupt = flag;
+    }
+
+    fn set_max_steps(&mut self, max_steps: Option<u64>) {
+        self.max_steps = max_steps;
+    }
+
+    fn set_tape_end(&mut self, tape_end: TapeEnd) {
+        self.tape_end = tape_end;
+        self.compile_options.tape_end = tape_end;
+    }
+
+    fn set_cell_modulus(&mut self, modulus: Option<u32>) -> Result<(), InterpreterError> {
+        if let Some(modulus) = modulus {
+            let max = self.cell_width.max_value() as u64 + 1;
+            if modulus == 0 || modulus as u64 > max {
+                return Err(InterpreterError::InvalidCellModulus { modulus, max });
+            }
+        }
+        self.cell_modulus = modulus;
+        Ok(())
+    }
+
+    fn set_arithmetic_mode(&mut self, arithmetic: ArithmeticMode) {
+        self.arithmetic = arithmetic;
+        self.compile_options.arithmetic = arithmetic;
+    }
+
+    fn set_debug_command(&mut self, enabled: bool) {
+        self.compile_options.debug_command = enabled;
+    }
+
+    fn set_extended(&mut self, enabled: bool) {
+        self.compile_options.extended = enabled;
+    }
+
+    fn register(&self) -> u32 {
+        self.register
+    }
+
+    fn set_procedures(&mut self, enabled: bool) {
+        self.compile_options.procedures = enabled;
+    }
+
+    fn set_tape_count(&mut self, count: usize) {
+        self.switch_tape(0);
+        let tape_size = self.cells.len();
+        let empty = || Tape { cells: vec![0; tape_size], cell_index: 0, origin: 0 };
+        self.tapes = if count > 1 { (0..count).map(|_| empty()).collect() } else { Vec::new() };
+        self.compile_options.multi_tape = count > 1;
+    }
+
+    fn active_tape(&self) -> usize {
+        self.active_tape
+    }
+
+    fn set_strict_ascii(&mut self, allowed_comments: Option<&[u8]>) {
+        self.compile_options.strict_ascii = allowed_comments.map(<[u8]>::to_vec);
+    }
+
+    fn set_opt_level(&mut self, opt_level: OptLevel) {
+        self.compile_options.opt_level = opt_level;
+    }
+
+    fn reset(&mut self) {
+        self.switch_tape(0);
+        for tape in &mut self.tapes {
+            tape.cells.fill(0);
+            tape.cell_index = 0;
+            tape.origin = 0;
+        }
+        self.cells.fill(0);
+        self.cell_index = 0;
+        self.origin = 0;
+        self.register = 0;
+        self.file_content.clear();
+        self.source_map.clear();
+        self.instructions.clear();
+        self.instruction_pointer = 0;
+        self.watch_hit = None;
+        self.history.clear();
+        self.procedures.clear();
+        self.call_stack.clear();
+        self.pending_line.clear();
+        self.profile = Profile::default();
+        if let Some(usage) = &mut self.cell_usage {
+            usage.clear();
+        }
+    }
+
+    fn transpile_c(&self) -> Result<String, InterpreterError> {
+        self.validate()?;
+        Ok(transpile::to_c(&self.file_content, self.cells.len(), self.cell_width))
+    }
+
+
+    fn increment(&mut self) -> Result<(), InterpreterError> {
+        self.add(1)
+    }
+
+    fn decrement(&mut self) -> Result<(), InterpreterError> {
+        self.add(-1)
+    }
+
+    fn add(&mut self, delta: i32) -> Result<(), InterpreterError> {
+        if delta >= 0 {
+            self.profile.increments += delta as u64;
+        } else {
+            self.profile.decrements += delta.unsigned_abs() as u64;
+        }
+
+        let max = self.cell_max() as i64;
+        let value = self.cells[self.cell_index] as i64 + delta as i64;
+        let value = match self.arithmetic {
+            ArithmeticMode::Wrap => value.rem_euclid(max + 1),
+            ArithmeticMode::Saturate => value.clamp(0, max),
+            ArithmeticMode::Error if !(0..=max).contains(&value) => {
+                let position = self.source_offset();
+                let (line, column, file) = self.source_location(position);
+                return Err(InterpreterError::CellOverflow { position, line, column, file });
+            }
+            ArithmeticMode::Error => value,
+        };
+        self.cells[self.cell_index] = value as u32;
+        self.record_usage(|usage| usage.written = true);
+        Ok(())
+    }
+
+    fn set_zero(&mut self) {
+        // `[-]` on a zero cell doesn't run its body, so it writes nothing.
+        if self.cells[self.cell_index] != 0 {
+            self.record_usage(|usage| usage.written = true);
+        }
+        self.cells[self.cell_index] = 0;
+    }
+
+    fn store_register(&mut self) {
+        self.register = self.cells[self.cell_index];
+    }
+
+    fn load_register(&mut self) {
+        self.cells[self.cell_index] = self.register;
+        self.record_usage(|usage| usage.written = true);
+    }
+
+    fn next_tape(&mut self) {
+        if !self.tapes.is_empty() {
+            self.switch_tape((self.active_tape + 1) % self.tapes.len());
+        }
+    }
+
+    fn previous_tape(&mut self) {
+        if !self.tapes.is_empty() {
+            let count = self.tapes.len();
+            self.switch_tape((self.active_tape + count - 1) % count);
+        }
+    }
+
+    fn seek_zero(&mut self, stride: i32) -> Result<(), InterpreterError> {
+        // Growing the tape always ends at a fresh zero cell. Otherwise the
+        // seek has visited every reachable cell after one pass of the tape.
+        let mut remaining = self.cells.len();
+        while self.cells[self.cell_index] != 0 {
+            let grows = matches!(self.tape_end, TapeEnd::Grow | TapeEnd::GrowBoth);
+            if remaining == 0 && !grows {
+                return self.spin_forever();
+            }
+            remaining = remaining.saturating_sub(1);
+            self.move_pointer(stride)?;
+        }
+        Ok(())
+    }
+
+    fn goto_next_cell(&mut self) -> Result<(), InterpreterError> {
+        self.move_pointer(1)
+    }
+
+    fn goto_previous_cell(&mut self) -> Result<(), InterpreterError> {
+        self.move_pointer(-1)
+    }
+
+    fn move_pointer(&mut self, delta: i32) -> Result<(), InterpreterError> {
+        if delta >= 0 {
+            self.profile.moves_right += delta as u64;
+        } else {
+            self.profile.moves_left += delta.unsigned_abs() as u64;
+        }
+
+        let len = match self.tape_end {
+            TapeEnd::Wrap => self.pointer_wrap.unwrap_or(self.cells.len()) as i64,
+            TapeEnd::Grow | TapeEnd::Error | TapeEnd::GrowBoth => self.cells.len() as i64,
+        };
+        let target = self.cell_index as i64 + delta as i64;
+        match self.tape_end {
+            _ if (0..len).contains(&target) => self.cell_index = target as usize,
+            TapeEnd::Wrap => self.cell_index = target.rem_euclid(len) as usize,
+            TapeEnd::Grow | TapeEnd::GrowBoth if target >= len => {
+                self.cells.resize(target as usize + 1, 0);
+                self.cell_index = target as usize;
+            }
+            TapeEnd::GrowBoth => {
+                let added = self.prepend_cells(target.unsigned_abs() as usize);
+                self.cell_index = (target + added as i64) as usize;
+            }
+            TapeEnd::Error if target >= len => {
+                self.cell_index = self.cells.len() - 1;
+                let (position, command) = (self.source_offset(), self.source_command('>'));
+                let (line, column, file) = self.source_location(position);
+                return Err(InterpreterError::PointerOverflow {
+                    position,
+                    line,
+                    column,
+                    file,
+                    command,
+                });
+            }
+            TapeEnd::Grow | TapeEnd::Error => {
+                self.cell_index = 0;
+                let (position, command) = (self.source_offset(), self.source_command('<'));
+                let (line, column, file) = self.source_location(position);
+                return Err(InterpreterError::PointerUnderflow {
+                    position,
+                    line,
+                    column,
+                    file,
+                    command,
+                });
+            }
+        }
+        self.profile.max_cell_index = self.profile.max_cell_index.max(self.cell_index);
+        Ok(())
+    }
+
+    fn add_at(&mut self, offset: i32, delta: i32) -> Result<(), InterpreterError> {
+        self.move_pointer(offset)?;
+        self.add(delta)?;
+        self.move_pointer(-offset)
+    }
+
+    fn open_loop(&mut self, target: usize) {
+        if self.cells[self.cell_index] == 0 {
+            self.profile.loop_jumps += 1;
+            self.instruction_pointer = target;
+        }
+    }
+
+    fn close_loop(&mut self, target: usize) {
+        if self.cells[self.cell_index] != 0 {
+            self.profile.loop_jumps += 1;
+            self.instruction_pointer = target;
+        }
+    }
+
+    fn define_procedure(&mut self, end: usize) {
+        self.procedures.insert(self.cells[self.cell_index], self.instruction_pointer);
+        self.instruction_pointer = end;
+    }
+
+    fn call_procedure(&mut self) -> Result<(), InterpreterError> {
+        let id = self.cells[self.cell_index];
+        let position = self.source_offset();
+        let Some(&start) = self.procedures.get(&id) else {
+            let (line, column, file) = self.source_location(position);
+            return Err(InterpreterError::UndefinedProcedure { id, position, line, column, file });
+        };
+        if self.call_stack.len() >= MAX_CALL_DEPTH {
+            let (line, column, file) = self.source_location(position);
+            let limit = MAX_CALL_DEPTH;
+            return Err(InterpreterError::CallDepthExceeded { limit, position, line, column, file });
+        }
+        self.call_stack.push(self.instruction_pointer);
+        self.instruction_pointer = start;
+        Ok(())
+    }
+
+    fn return_from_procedure(&mut self) {
+        if let Some(call) = self.call_stack.pop() {
+            self.instruction_pointer = call;
+        }
+    }
+
+    fn print(&mut self) -> Result<(), InterpreterError> {
+        self.profile.outputs += 1;
+        self.record_usage(|usage| usage.printed = true);
+        if self.count_only {
+            return Ok(());
+        }
+        if self.numeric_io {
+            write!(self.output, "{} ", self.display_value(self.cell_index))?;
+            return Ok(());
+        }
+        let byte = self.cells[self.cell_index] as u8;
+        if byte == b'\n' && self.crlf_output {
+            self.output.write_all(b"\r\n")?;
+        } else {
+            self.output.write_all(&[byte])?;
+        }
+        Ok(())
+    }
+
+    fn input(&mut self) -> Result<(), InterpreterError> {
+        self.profile.inputs += 1;
+        self.record_usage(|usage| usage.written = true);
+        if self.count_only {
+            self.cells[self.cell_index] = 0;
+            return Ok(());
+        }
+        // Make sure a prompt printed by the program is visible before blocking.
+        self.output.flush()?;
+        if self.numeric_io {
+            match self.read_number()? {
+                Some(value) => self.cells[self.cell_index] = value,
+                None => self.store_eof(),
+            }
+            return Ok(());
+        }
+        let width = if self.wide_input { self.cell_width.bytes() } else { 1 };
+        let mut input = [0; 4];
+        let mut filled = 0;
+        while filled < width {
+            if let Some(byte) = self.input_queue.pop_front() {
+                input[filled] = byte;
+                filled += 1;
+                continue;
+            }
+            match self.input.read(&mut input[filled..width])? {
+                0 => break,
+                read => filled += read,
+            }
+        }
+        if filled == width {
+            let value = u32::from_le_bytes(input);
+            self.cells[self.cell_index] = self.cell_modulus.map_or(value, |modulus| value % modulus);
+            return Ok(());
+        }
+        self.store_eof();
+        Ok(())
+    }
+
+    fn debug_dump(&mut self) -> Result<(), InterpreterError> {
+        let start = self.cell_index.saturating_sub(DEBUG_DUMP_WINDOW / 2);
+        let dump = self.dump_tape(self.logical(start), DEBUG_DUMP_WINDOW);
+        self.debug_output.write_all(dump.as_bytes())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read, Write};
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use serde_json::{json, Value};
+    use crate::buffering::tests::FlushingWriter;
+    use crate::{
+        generate, group_digits, line_column, minimize, run_batch, ArithmeticMode, CapturedOutput,
+        CellWidth, Dialect, EofBehavior, ExecutedStep, Interpreter, InterpreterBuilder,
+        InterpreterError, InterpreterState, Op, OptLevel, Profile, ProgramSource, RunResult,
+        RunStatus, SeededInput, TapeEnd, WatchHit, MAX_CALL_DEPTH,
+    };
+
+    const HELLO_WORLD: &str = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+
+    #[test]
+    fn increment() {
+        let mut i = InterpreterState::new();
+        i.increment().unwrap();
+
+        assert_eq!(i.cells[0], 1);
+    }
+
+    #[test]
+    fn increment_multiple_times() {
+        let mut i = InterpreterState::new();
+        i.increment().unwrap();
+        i.increment().unwrap();
+        i.increment().unwrap();
+
+        assert_eq!(i.cells[0], 3);
+    }
+
+    #[test]
+    fn increment_wraps_at_max() {
+        let mut i = InterpreterState::new();
+        i.cells[0] = 255;
+
+        i.increment().unwrap();
+
+        assert_eq!(i.cells[0], 0);
+    }
+
+    #[test]
+    fn decrement() {
+        let mut i = InterpreterState::new();
+        i.increment().unwrap();
+        i.increment().unwrap();
+
+        i.decrement().unwrap();
+        assert_eq!(i.cells[0], 1);
+    }
+
+    #[test]
+    fn goto_next_call_at_end() {
+        let mut i = InterpreterState::new();
+        i.cell_index = 29999;
+
+        i.goto_next_cell().unwrap();
+
+        assert_eq!(i.cell_index, 0);
+    }
+
+    #[test]
+    fn goto_next_call_at_beginning() {
+        let mut i = InterpreterState::new();
+        i.goto_next_cell().unwrap();
+
+        assert_eq!(i.cell_index, 1);
+    }
+
+    #[test]
+    fn goto_previous_cell_at_beginning() {
+        let mut i = InterpreterState::new();
+        i.goto_previous_cell().unwrap();
+
+        assert_eq!(i.cell_index, 29999);
+    }
+
+    #[test]
+    fn goto_previous_cell_at_end() {
+        let mut i = InterpreterState::new();
+        i.goto_previous_cell().unwrap();
+
+        assert_eq!(i.cell_index, 29999);
+    }
+
+    #[test]
+    fn with_tape_size_sets_length() {
+        let i = InterpreterState::with_tape_size(10);
+
+        assert_eq!(i.cells.len(), 10);
+    }
+
+    #[test]
+    fn zero_tape_size_gives_a_single_cell() {
+        let mut i = InterpreterState::with_tape_size(0);
+
+        i.run_string("++>+<-").unwrap();
+
+        assert_eq!(i.tape(), &[2]);
+        assert_eq!(i.render_tape(2, false), "    0: [2]");
+    }
+
+    #[test]
+    fn goto_next_cell_wraps_at_configured_size() {
+        let mut i = InterpreterState::with_tape_size(10);
+        i.cell_index = 9;
+
+        i.goto_next_cell().unwrap();
+
+        assert_eq!(i.cell_index, 0);
+    }
+
+    #[test]
+    fn goto_previous_cell_wraps_at_configured_size() {
+        let mut i = InterpreterState::with_tape_size(10);
+        i.goto_previous_cell().unwrap();
+
+        assert_eq!(i.cell_index, 9);
+    }
+
+    #[test]
+    fn increment_past_255_in_sixteen_bit_mode() {
+        let mut i = InterpreterState::with_config(10, CellWidth::Sixteen);
+        i.cells[0] = 255;
+
+        i.increment().unwrap();
+
+        assert_eq!(i.cells[0], 256);
+    }
+
+    #[test]
+    fn increment_wraps_at_65535_in_sixteen_bit_mode() {
+        let mut i = InterpreterState::with_config(10, CellWidth::Sixteen);
+        i.cells[0] = 65535;
+
+        i.increment().unwrap();
+
+        assert_eq!(i.cells[0], 0);
+    }
+
+    #[test]
+    fn decrement_wraps_to_max_in_thirty_two_bit_mode() {
+        let mut i = InterpreterState::with_config(10, CellWidth::ThirtyTwo);
+        i.decrement().unwrap();
+
+        assert_eq!(i.cells[0], u32::MAX);
+    }
+
+    #[test]
+    fn parse_reports_unclosed_loop() {
+        let mut i = InterpreterState::new();
+        let result = i.run_string("+[+");
+
+        assert!(matches!(result, Err(InterpreterError::UnmatchedOpen { position: 1, .. })));
+    }
+
+    #[test]
+    fn parse_reports_stray_close() {
+        let mut i = InterpreterState::new();
+        let result = i.run_string("+]");
+
+        assert!(matches!(result, Err(InterpreterError::UnmatchedClose { position: 1, .. })));
+    }
+
+    #[test]
+    fn stray_close_stops_before_anything_runs() {
+        let mut i = InterpreterState::new();
+        let result = i.run_string("+]+");
+
+        assert!(matches!(result, Err(InterpreterError::UnmatchedClose { position: 1, .. })));
+        assert_eq!(i.current_cell(), 0);
+        assert_eq!(i.profile().steps, 0);
+
+        assert!(i.eval_line("+]+\n").is_err());
+        assert_eq!(i.current_cell(), 0);
+        assert!(!i.is_line_pending());
+    }
+
+    #[test]
+    fn load_file_reports_missing_file() {
+        let mut i = InterpreterState::new();
+
+        let result = i.load_file("does/not/exist.bf");
+
+        assert!(matches!(result, Err(InterpreterError::FileNotFound(_))));
+    }
+
+    #[test]
+    fn prepare_matches_nested_brackets() {
+        let mut i = InterpreterState::new();
+        i.load_string("[[]]");
+
+        i.prepare().unwrap();
+
+        let ops: Vec<Op> = i.instructions.iter().map(|instruction| instruction.op).collect();
+        assert_eq!(ops, vec![Op::LoopStart(3), Op::LoopStart(2), Op::LoopEnd(1), Op::LoopEnd(0)]);
+    }
+
+    #[test]
+    fn parse_runs_multiply_loop() {
+        let mut i = InterpreterState::new();
+        i.run_string("++++++[>+++++++<-]").unwrap();
+
+        assert_eq!(i.cells[0], 0);
+        assert_eq!(i.cells[1], 42);
+    }
+
+    #[test]
+    fn parse_skips_loop_when_cell_is_zero() {
+        let mut i = InterpreterState::new();
+        i.run_string("[+++]+").unwrap();
+
+        assert_eq!(i.cells[0], 1);
+    }
+
+    #[test]
+    fn parse_runs_two_levels_of_nested_loops() {
+        let mut i = InterpreterState::new();
+        i.run_string("+++[>++++[>+++++<-]<-]").unwrap();
+
+        assert_eq!(i.cells[0], 0);
+        assert_eq!(i.cells[1], 0);
+        assert_eq!(i.cells[2], 60);
+    }
+
+    #[test]
+    fn eval_line_keeps_tape_between_lines() {
+        let mut i = InterpreterState::new();
+        i.eval_line("+++>").unwrap();
+        i.eval_line("++<+").unwrap();
+
+        assert_eq!(i.cells[0], 4);
+        assert_eq!(i.cells[1], 2);
+        assert_eq!(i.pointer(), 0);
+    }
+
+    #[test]
+    fn eval_line_completes_loop_across_lines() {
+        let mut i = InterpreterState::new();
+        i.eval_line("+++[>++").unwrap();
+
+        assert!(i.is_line_pending());
+        assert_eq!(i.cells[1], 0);
+
+        i.eval_line("<-]").unwrap();
+
+        assert!(!i.is_line_pending());
+        assert_eq!(i.cells[0], 0);
+        assert_eq!(i.cells[1], 6);
+    }
+
+    #[test]
+    fn step_executes_one_command_at_a_time() {
+        let mut i = InterpreterState::new();
+        i.load_string("+ comment >+");
+        i.prepare().unwrap();
+
+        assert_eq!(i.step().unwrap(), Some('+'));
+        assert_eq!(i.cells[0], 1);
+        assert_eq!(i.step().unwrap(), Some('>'));
+        assert_eq!(i.cell_index, 1);
+        assert_eq!(i.step().unwrap(), Some('+'));
+        assert_eq!(i.cells[1], 1);
+        assert_eq!(i.step().unwrap(), None);
+    }
+
+    #[test]
+    fn step_follows_loop_jumps() {
+        let mut i = InterpreterState::new();
+        i.load_string("++[->+<]");
+        i.prepare().unwrap();
+
+        let executed: Vec<char> = std::iter::from_fn(|| i.step().unwrap()).collect();
+
+        assert_eq!(
+            executed,
+            vec!['+', '[', '-', '>', '+', '<', ']', '-', '>', '+', '<', ']']
+        );
+        assert_eq!(i.cells[..2], [0, 2]);
+    }
+
+    #[test]
+    fn print_writes_to_configured_output() {
+        let buffer = CapturedOutput::default();
+        let mut i = InterpreterState::new();
+        i.set_output(Box::new(buffer.clone()));
+
+        i.run_string(HELLO_WORLD).unwrap();
+
+        assert_eq!(*buffer.0.borrow(), b"Hello World!\n");
+    }
+
+    #[test]
+    fn print_writes_raw_bytes() {
+        let buffer = CapturedOutput::default();
+        let mut i = InterpreterState::new();
+        i.set_output(Box::new(buffer.clone()));
+        i.cells[0] = 0xC3;
+        i.cells[1] = 0xA9;
+
+        i.run_string(".>.").unwrap();
+
+        assert_eq!(*buffer.0.borrow(), "é".as_bytes());
+    }
+
+    #[test]
+    fn profile_counts_executed_operations() {
+        let mut i = InterpreterState::new();
+        i.set_output(Box::new(CapturedOutput::default()));
+
+        i.run_string("++[>+<-]>.").unwrap();
+
+        assert_eq!(
+            *i.profile(),
+            Profile {
+                increments: 4,
+                decrements: 2,
+                moves_right: 3,
+                moves_left: 2,
+                outputs: 1,
+                inputs: 0,
+                loop_jumps: 1,
+                steps: 15,
+                max_cell_index: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn transpile_c_emits_loop_structure() {
+        let mut i = InterpreterState::with_tape_size(100);
+        i.load_string("+[->+<]");
+
+        let source = i.transpile_c().unwrap();
+
+        assert!(source.contains("static unsigned char tape[100];"));
+        assert!(source.contains(
+            "    ++*p;\n    while (*p) {\n        --*p;\n        ++p;\n        ++*p;\n        --p;\n    }\n"
+        ));
+    }
+
+    #[test]
+    fn transpile_c_rejects_unbalanced_brackets() {
+        let mut i = InterpreterState::new();
+        i.load_string("+[");
+
+        assert!(matches!(i.transpile_c(), Err(InterpreterError::UnmatchedOpen { position: 1, .. })));
+    }
+
+    #[test]
+    fn input_reads_a_byte() {
+        let mut i = InterpreterState::new();
+
+        i.set_input(Box::new(&b"A"[..]));
+
+        i.input().unwrap();
+
+        assert_eq!(i.cells[0], 65);
+    }
+
+    #[test]
+    fn input_at_eof_sets_zero() {
+        let mut i = InterpreterState::new();
+        i.cells[0] = 7;
+        i.set_eof_behavior(EofBehavior::Zero);
+        i.set_input(Box::new(&b""[..]));
+
+        i.input().unwrap();
+
+        assert_eq!(i.cells[0], 0);
+    }
+
+    #[test]
+    fn input_at_eof_sets_minus_one() {
+        let mut i = InterpreterState::new();
+        i.set_eof_behavior(EofBehavior::MinusOne);
+        i.set_input(Box::new(&b""[..]));
+
+        i.input().unwrap();
+
+        assert_eq!(i.cells[0], 255);
+    }
+
+    #[test]
+    fn input_at_eof_leaves_cell_unchanged() {
+        let mut i = InterpreterState::new();
+        i.cells[0] = 7;
+        i.set_eof_behavior(EofBehavior::Unchanged);
+        i.set_input(Box::new(&b""[..]));
+
+        i.input().unwrap();
+
+        assert_eq!(i.cells[0], 7);
+    }
+
+    #[test]
+    fn input_reads_from_configured_source() {
+        let mut i = InterpreterState::new();
+        i.set_input(Box::new(Cursor::new(vec![3, 5, 8])));
+
+        i.run_string(",>,>,").unwrap();
+
+        assert_eq!(i.cells[..4], [3, 5, 8, 0]);
+    }
+
+    #[test]
+    fn validate_reports_stray_close() {
+        let mut i = InterpreterState::new();
+        i.load_string("+[]]");
+
+        assert!(matches!(i.validate(), Err(InterpreterError::UnmatchedClose { position: 3, .. })));
+    }
+
+    #[test]
+    fn validate_reports_first_unclosed_open() {
+        let mut i = InterpreterState::new();
+        i.load_string("+[[[]");
+
+        assert!(matches!(i.validate(), Err(InterpreterError::UnmatchedOpen { position: 1, .. })));
+    }
+
+    #[test]
+    fn validate_accepts_nested_brackets() {
+        let mut i = InterpreterState::new();
+        i.load_string("[[]>[[]]]");
+
+        assert!(i.validate().is_ok());
+    }
+
+    #[test]
+    fn line_column_counts_lines_and_columns() {
+        let source = "+++\n>>\n  <<]";
+
+        assert_eq!(line_column(source, 0), (1, 1));
+        assert_eq!(line_column(source, 2), (1, 3));
+        assert_eq!(line_column(source, 4), (2, 1));
+        assert_eq!(line_column(source, 11), (3, 5));
+    }
+
+    #[test]
+    fn unmatched_close_reports_line_and_column() {
+        let mut i = InterpreterState::new();
+        i.load_string("+\n+\n  +]");
+
+        let err = i.validate().unwrap_err();
+
+        assert_eq!(err.to_string(), "error at line 3, column 4: unmatched ']'");
+    }
+
+    #[test]
+    fn reset_clears_tape_and_pointers() {
+        let mut i = InterpreterState::new();
+        i.run_string("+++>++>+").unwrap();
+
+        i.reset();
+
+        assert!(i.cells.iter().all(|&cell| cell == 0));
+        assert_eq!(i.cell_index, 0);
+        assert_eq!(i.instruction_pointer, 0);
+        assert!(i.file_content.is_empty());
+        assert!(i.instructions.is_empty());
+        assert_eq!(*i.profile(), Profile::default());
+    }
+
+    #[test]
+    fn signed_cells_overflow_from_127_to_minus_128() {
+        let mut i = InterpreterState::new();
+        i.set_signed_cells(true);
+        i.cells[0] = 127;
+
+        i.increment().unwrap();
+
+        assert_eq!(i.cells[0], 0x80);
+        assert_eq!(i.cell_display_value(0), -128);
+    }
+
+    #[test]
+    fn signed_cells_decrement_zero_to_minus_one() {
+        let mut i = InterpreterState::new();
+        i.set_signed_cells(true);
+
+        i.decrement().unwrap();
+
+        assert_eq!(i.cells[0], 0xFF);
+        assert_eq!(i.cell_display_value(0), -1);
+    }
+
+    #[test]
+    fn unsigned_cells_display_raw_value() {
+        let mut i = InterpreterState::new();
+        i.decrement().unwrap();
+
+        assert_eq!(i.cell_display_value(0), 255);
+    }
+
+    #[test]
+    fn dump_tape_formats_hex_and_ascii() {
+        let mut i = InterpreterState::new();
+        i.cells[0] = b'H' as u32;
+        i.cells[1] = b'i' as u32;
+        i.cells[2] = 0x01;
+        i.cell_index = 1;
+
+        assert_eq!(
+            i.dump_tape(0, 4),
+            "00000000  48[69]01 00                                      |Hi..|\n"
+        );
+    }
+
+    #[test]
+    fn dump_tape_splits_rows_of_sixteen() {
+        let mut i = InterpreterState::with_tape_size(20);
+        i.cell_index = 15;
+
+        let dump = i.dump_tape(0, 20);
+        let rows: Vec<&str> = dump.lines().collect();
+
+        assert_eq!(rows.len(), 2);
+        assert!(rows[0].ends_with("[00] |................|"));
+        assert!(rows[1].starts_with("00000010  00 00 00 00 "));
+    }
+
+    #[test]
+    fn used_tape_len_covers_last_non_zero_cell() {
+        let mut i = InterpreterState::new();
+        i.run_string(">>>+<").unwrap();
+
+        assert_eq!(i.used_tape_len(), 4);
+    }
+
+    #[test]
+    fn max_steps_stops_infinite_loop() {
+        let mut i = InterpreterState::new();
+        i.set_max_steps(Some(1000));
+
+        let result = i.run_string("+[]");
+
+        assert!(matches!(result, Err(InterpreterError::StepLimitExceeded { limit: 1000 })));
+        assert_eq!(i.profile().steps, 1000);
+    }
+
+    #[test]
+    fn max_steps_allows_program_within_limit() {
+        let mut i = InterpreterState::new();
+        i.set_max_steps(Some(3));
+
+        assert!(i.run_string("+++").is_ok());
+    }
+
+    #[test]
+    fn grow_mode_extends_tape_to_the_right() {
+        let mut i = InterpreterState::with_tape_size(10);
+        i.set_tape_end(TapeEnd::Grow);
+
+        for _ in 0..40000 {
+            i.goto_next_cell().unwrap();
+        }
+        i.increment().unwrap();
+
+        assert_eq!(i.cells.len(), 40001);
+        assert_eq!(i.cell_index, 40000);
+        assert_eq!(i.cells[40000], 1);
+    }
+
+    #[test]
+    fn grow_mode_rejects_moving_left_of_start() {
+        let mut i = InterpreterState::new();
+        i.set_tape_end(TapeEnd::Grow);
+
+        let result = i.run_string("+<");
+
+        assert!(matches!(result, Err(InterpreterError::PointerUnderflow { position: 1, .. })));
+    }
+
+    #[test]
+    fn error_mode_rejects_moving_left_of_start() {
+        let mut i = InterpreterState::new();
+        i.set_tape_end(TapeEnd::Error);
+
+        let result = i.run_string("+ <");
+
+        assert!(matches!(result, Err(InterpreterError::PointerUnderflow { position: 2, .. })));
+        assert_eq!(i.cell_index, 0);
+    }
+
+    #[test]
+    fn error_mode_rejects_moving_right_of_end() {
+        let mut i = InterpreterState::with_tape_size(3);
+        i.set_tape_end(TapeEnd::Error);
+
+        let result = i.run_string(">>>");
+
+        assert!(matches!(result, Err(InterpreterError::PointerOverflow { position: 2, .. })));
+        assert_eq!(i.cell_index, 2);
+    }
+
+    #[test]
+    fn pointer_errors_name_the_command_that_moved() {
+        let mut i = InterpreterState::with_tape_size(1);
+        i.set_tape_end(TapeEnd::Error);
+
+        let err = i.run_string("+ <").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "error at line 1, column 3: '<' at char 2 moved the pointer left of the first cell"
+        );
+
+        i.reset();
+        let result = i.goto_next_cell();
+        assert!(matches!(result, Err(InterpreterError::PointerOverflow { command: '>', .. })));
+    }
+
+    #[test]
+    fn wrap_mode_wraps_at_both_ends() {
+        let mut i = InterpreterState::with_tape_size(3);
+        i.set_tape_end(TapeEnd::Wrap);
+
+        i.run_string("<").unwrap();
+        assert_eq!(i.cell_index, 2);
+
+        i.run_string(">").unwrap();
+        assert_eq!(i.cell_index, 0);
+    }
+
+    #[test]
+    fn add_wraps_by_net_delta() {
+        let mut i = InterpreterState::new();
+        i.cells[0] = 250;
+
+        i.add(10).unwrap();
+        assert_eq!(i.cells[0], 4);
+
+        i.add(-5).unwrap();
+        assert_eq!(i.cells[0], 255);
+    }
+
+    #[test]
+    fn move_pointer_wraps_by_net_delta() {
+        let mut i = InterpreterState::with_tape_size(10);
+
+        i.move_pointer(-3).unwrap();
+        assert_eq!(i.cell_index, 7);
+
+        i.move_pointer(25).unwrap();
+        assert_eq!(i.cell_index, 2);
+    }
+
+    #[test]
+    fn merged_runs_produce_same_tape() {
+        let mut i = InterpreterState::new();
+
+        i.run_string("+++++>>>++<<+--<-").unwrap();
+
+        assert_eq!(i.cells[..4], [4, 255, 0, 2]);
+        assert_eq!(i.cell_index, 0);
+    }
+
+    #[test]
+    fn clear_loop_zeroes_cell_in_one_op() {
+        let mut i = InterpreterState::new();
+        i.cells[0] = 200;
+
+        i.run_string("[-]").unwrap();
+
+        assert_eq!(i.cells[0], 0);
+        assert_eq!(i.instructions.len(), 1);
+        assert_eq!(i.profile().steps, 401);
+    }
+
+    #[test]
+    fn steps_count_source_commands_at_every_opt_level() {
+        let program = "+++[-]>+-<>>+<<[>]+>+[+]<<<[>+<-]>[<]>.[-][<]++";
+        let steps = [OptLevel::None, OptLevel::Basic, OptLevel::Full, OptLevel::Aggressive].map(
+            |opt_level| {
+                let mut i = InterpreterState::new();
+                i.set_output(Box::new(CapturedOutput::default()));
+                i.set_opt_level(opt_level);
+                i.run_string(program).unwrap();
+                i.profile().steps
+            },
+        );
+        assert!(steps.iter().all(|&count| count == steps[0]), "{steps:?}");
+
+        for opt_level in [OptLevel::None, OptLevel::Basic] {
+            let mut i = InterpreterState::new();
+            i.set_output(Box::new(CapturedOutput::default()));
+            i.set_opt_level(opt_level);
+            i.set_max_steps(Some(10));
+            let result = i.run_string(&format!("{}.", "+".repeat(66)));
+            assert!(matches!(result, Err(InterpreterError::StepLimitExceeded { limit: 10 })));
+        }
+    }
+
+    #[test]
+    fn profiles_count_replaced_loops_like_unoptimized_ones() {
+        let program = "++[-]>+++>+>+<<[>]<[+]>>[-]";
+        let profiles = [OptLevel::None, OptLevel::Basic, OptLevel::Full, OptLevel::Aggressive]
+            .map(|opt_level| {
+                let mut i = InterpreterState::new();
+                i.set_opt_level(opt_level);
+                i.run_string(program).unwrap();
+                i.profile().clone()
+            });
+
+        assert_eq!((profiles[0].loop_jumps, profiles[0].increments), (258, 262));
+        for profile in &profiles[1..] {
+            assert_eq!(profile, &profiles[0]);
+        }
+    }
+
+    #[test]
+    fn load_file_appends_multiple_files() {
+        let dir = std::env::temp_dir();
+        let first = dir.join("beefin_load_file_appends_first.bf");
+        let second = dir.join("beefin_load_file_appends_second.bf");
+        std::fs::write(&first, "++++++++[>++++++++<-]").unwrap();
+        std::fs::write(&second, ">+.+.").unwrap();
+
+        let buffer = CapturedOutput::default();
+        let mut i = InterpreterState::new();
+        i.set_output(Box::new(buffer.clone()));
+        i.load_file(first.to_str().unwrap()).unwrap();
+        i.load_file(second.to_str().unwrap()).unwrap();
+        i.parse().unwrap();
+
+        assert_eq!(*buffer.0.borrow(), b"AB");
+    }
+
+    #[test]
+    fn brackets_are_matched_across_loaded_files() {
+        let dir = std::env::temp_dir();
+        let first = dir.join("beefin_brackets_across_first.bf");
+        let second = dir.join("beefin_brackets_across_second.bf");
+        std::fs::write(&first, "+++[>+").unwrap();
+        std::fs::write(&second, "<-]").unwrap();
+
+        let mut i = InterpreterState::new();
+        i.load_file(first.to_str().unwrap()).unwrap();
+        i.load_file(second.to_str().unwrap()).unwrap();
+        i.parse().unwrap();
+
+        assert_eq!(i.cells[..2], [0, 3]);
+    }
+
+    #[test]
+    fn tape_end_parses_known_values() {
+        assert_eq!("wrap".parse(), Ok(TapeEnd::Wrap));
+        assert_eq!("grow".parse(), Ok(TapeEnd::Grow));
+        assert_eq!("error".parse(), Ok(TapeEnd::Error));
+        assert!("bounce".parse::<TapeEnd>().unwrap_err().contains("wrap, grow, error"));
+    }
+
+    const PROSE_PROGRAM: &str = "This program prints the letter A\n\
+        First we set up a counter of eight ++++++++ and then loop over it [\n\
+        moving right > adding eight ++++++++ and moving back < and counting\n\
+        down - ] after which we step right > add one + and print the result .";
+
+    #[test]
+    fn prose_program_runs_only_its_commands() {
+        let buffer = CapturedOutput::default();
+        let mut i = InterpreterState::new();
+        i.set_output(Box::new(buffer.clone()));
+
+        i.run_string(PROSE_PROGRAM).unwrap();
+
+        assert_eq!(*buffer.0.borrow(), b"A");
+        assert_eq!(i.instructions.len(), 10);
+    }
+
+    #[test]
+    fn errors_in_prose_program_point_at_source_offset() {
+        let mut i = InterpreterState::new();
+        i.set_tape_end(TapeEnd::Error);
+        let program = "a comment and then < moves left";
+
+        let result = i.run_string(program);
+
+        assert!(matches!(result, Err(InterpreterError::PointerUnderflow { position: 19, .. })));
+        assert_eq!(&program[19..20], "<");
+    }
+
+    #[test]
+    fn hash_dumps_tape_only_when_enabled() {
+        let buffer = CapturedOutput::default();
+        let mut i = InterpreterState::new();
+        i.set_debug_output(Box::new(buffer.clone()));
+
+        i.run_string("+#").unwrap();
+        assert!(buffer.0.borrow().is_empty());
+
+        i.set_debug_command(true);
+        i.run_string("+#").unwrap();
+        assert_eq!(
+            String::from_utf8(buffer.0.borrow().clone()).unwrap(),
+            i.dump_tape(0, 16)
+        );
+        assert!(buffer.0.borrow().starts_with(b"00000000 [02]00"));
+    }
+
+    #[test]
+    fn tape_exposes_computed_cells() {
+        let mut i = InterpreterState::new();
+
+        i.run_string("++++++[>+++++++<-]>").unwrap();
+
+        assert_eq!(i.pointer(), 1);
+        assert_eq!(&i.tape()[..3], &[0, 42, 0]);
+    }
+
+    #[test]
+    fn load_file_decompresses_gzip() {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+
+        let dir = std::env::temp_dir();
+        let plain = dir.join("beefin_load_file_gzip.bf");
+        let compressed = dir.join("beefin_load_file_gzip.bf.gz");
+        std::fs::write(&plain, HELLO_WORLD).unwrap();
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(HELLO_WORLD.as_bytes()).unwrap();
+        std::fs::write(&compressed, encoder.finish().unwrap()).unwrap();
+
+        let mut outputs = Vec::new();
+        for path in [&plain, &compressed] {
+            let buffer = CapturedOutput::default();
+            let mut i = InterpreterState::new();
+            i.set_output(Box::new(buffer.clone()));
+            i.load_file(path.to_str().unwrap()).unwrap();
+            i.parse().unwrap();
+            outputs.push(buffer.0.take());
+        }
+
+        assert_eq!(outputs[0], b"Hello World!\n");
+        assert_eq!(outputs[0], outputs[1]);
+    }
+
+    #[test]
+    fn render_tape_brackets_current_cell_without_color() {
+        let mut i = InterpreterState::new();
+
+        i.run_string(">>+++>++++++++<").unwrap();
+
+        assert_eq!(i.render_tape(2, false), "    0:  0  0 [3] 8  0 ");
+        assert_eq!(i.render_tape(0, false), "    2: [3]");
+    }
+
+    #[test]
+    fn render_tape_highlights_current_cell_with_color() {
+        let mut i = InterpreterState::new();
+
+        i.run_string("+").unwrap();
+
+        assert_eq!(i.render_tape(1, true), "    0: \x1b[1;30;43m 1 \x1b[0m 0 ");
+    }
+
+    #[test]
+    fn display_summarises_state() {
+        let mut i = InterpreterState::new();
+        i.set_output(Box::new(CapturedOutput::default()));
+
+        i.run_string(HELLO_WORLD).unwrap();
+
+        assert_eq!(
+            i.to_string(),
+            "pointer: 6, cell: 10, non-zero cells: 5, program: 106 bytes"
+        );
+    }
+
+    #[test]
+    fn wide_input_reads_little_endian_cell() {
+        let mut i = InterpreterState::with_config(10, CellWidth::Sixteen);
+        i.set_wide_input(true);
+        i.set_input(Box::new(&[0x34, 0x12][..]));
+
+        i.run_string(",").unwrap();
+
+        assert_eq!(i.current_cell(), 0x1234);
+    }
+
+    #[test]
+    fn wide_input_treats_partial_read_as_eof() {
+        let mut i = InterpreterState::with_config(10, CellWidth::Sixteen);
+        i.set_wide_input(true);
+        i.set_eof_behavior(EofBehavior::MinusOne);
+        i.set_input(Box::new(&[0x34][..]));
+
+        i.run_string(",").unwrap();
+
+        assert_eq!(i.current_cell(), 0xffff);
+    }
+
+    #[test]
+    fn trace_writes_one_line_per_step() {
+        let buffer = CapturedOutput::default();
+        let mut i = InterpreterState::new();
+        i.set_trace(Some(Box::new(buffer.clone())));
+
+        i.run_string("+> +<-").unwrap();
+
+        let trace = String::from_utf8(buffer.0.take()).unwrap();
+        assert_eq!(trace.lines().count() as u64, i.profile().steps);
+        assert_eq!(trace.lines().nth(3), Some("offset=4 op='<' ptr=0 cell=1"));
+    }
+
+    #[test]
+    fn load_until_sentinel_reads_program_then_input() {
+        let buffer = CapturedOutput::default();
+        let mut i = InterpreterState::new();
+        i.set_output(Box::new(buffer.clone()));
+        let stream = Cursor::new(b",+.,+.\n%%\nHI".to_vec());
+
+        i.load_until_sentinel(Box::new(stream)).unwrap();
+        i.parse().unwrap();
+
+        assert_eq!(*buffer.0.borrow(), b"IJ");
+    }
+
+    #[test]
+    fn wrap_arithmetic_wraps_at_both_ends() {
+        let mut i = InterpreterState::new();
+
+        i.decrement().unwrap();
+        assert_eq!(i.current_cell(), 255);
+        i.increment().unwrap();
+        assert_eq!(i.current_cell(), 0);
+    }
+
+    #[test]
+    fn saturating_arithmetic_stays_at_both_ends() {
+        let mut i = InterpreterState::new();
+        i.set_arithmetic_mode(ArithmeticMode::Saturate);
+
+        i.decrement().unwrap();
+        assert_eq!(i.current_cell(), 0);
+        i.add(300).unwrap();
+        assert_eq!(i.current_cell(), 255);
+        i.increment().unwrap();
+        assert_eq!(i.current_cell(), 255);
+    }
+
+    #[test]
+    fn saturating_arithmetic_applies_each_command_of_a_run() {
+        let mut i = InterpreterState::new();
+        i.set_arithmetic_mode(ArithmeticMode::Saturate);
+        i.cells[0] = 255;
+
+        i.run_string("+++--").unwrap();
+
+        assert_eq!(i.current_cell(), 253);
+    }
+
+    #[test]
+    fn error_arithmetic_rejects_overflow_inside_a_run() {
+        let mut i = InterpreterState::new();
+        i.set_arithmetic_mode(ArithmeticMode::Error);
+        i.cells[0] = 254;
+
+        assert!(matches!(
+            i.run_string("+++--"),
+            Err(InterpreterError::CellOverflow { position: 1, .. })
+        ));
+        assert_eq!(i.current_cell(), 255);
+    }
+
+    #[test]
+    fn error_arithmetic_rejects_overflow_at_both_ends() {
+        let mut i = InterpreterState::new();
+        i.set_arithmetic_mode(ArithmeticMode::Error);
+
+        assert!(matches!(
+            i.run_string(">+<  -"),
+            Err(InterpreterError::CellOverflow { position: 5, .. })
+        ));
+        assert_eq!(i.current_cell(), 0);
+
+        i.add(255).unwrap();
+        assert!(matches!(i.increment(), Err(InterpreterError::CellOverflow { .. })));
+        assert_eq!(i.current_cell(), 255);
+    }
+
+    #[test]
+    fn max_tape_index_tracks_highest_cell_reached() {
+        let mut i = InterpreterState::new();
+
+        i.run_string(&format!("{}+{}>", ">".repeat(50), "<".repeat(10))).unwrap();
+
+        assert_eq!(i.pointer(), 41);
+        assert_eq!(i.max_tape_index(), 50);
+    }
+
+    #[test]
+    fn load_file_ignores_shebang_line() {
+        let path = std::env::temp_dir().join("beefin_load_file_shebang.bf");
+        std::fs::write(&path, format!("#!/usr/bin/env beefin\n{HELLO_WORLD}")).unwrap();
+
+        let buffer = CapturedOutput::default();
+        let mut i = InterpreterState::new();
+        i.set_output(Box::new(buffer.clone()));
+        i.set_debug_command(true);
+        i.set_debug_output(Box::new(buffer.clone()));
+        i.load_file(path.to_str().unwrap()).unwrap();
+        i.parse().unwrap();
+
+        assert_eq!(*buffer.0.borrow(), b"Hello World!\n");
+    }
+
+    #[test]
+    fn shebang_keeps_error_positions() {
+        let path = std::env::temp_dir().join("beefin_shebang_error_position.bf");
+        std::fs::write(&path, "#!/usr/bin/env beefin\n+]").unwrap();
+
+        let mut i = InterpreterState::new();
+        i.load_file(path.to_str().unwrap()).unwrap();
+
+        assert!(matches!(
+            i.validate(),
+            Err(InterpreterError::UnmatchedClose { position: 23, line: 2, column: 2, file: None })
+        ));
+    }
+
+    #[test]
+    fn step_over_runs_clear_loop_in_one_call() {
+        let mut i = InterpreterState::new();
+        i.load_string("+++[-]+");
+        i.prepare().unwrap();
+
+        assert_eq!(i.step_over().unwrap(), Some('+'));
+        assert_eq!(i.step_over().unwrap(), Some('['));
+        assert_eq!(i.current_cell(), 0);
+    }
+
+    #[test]
+    fn step_over_runs_whole_loop() {
+        let mut i = InterpreterState::new();
+        i.load_string("+++[->++<]>");
+        i.prepare().unwrap();
+        i.step().unwrap();
+
+        assert_eq!(i.step_over().unwrap(), Some('['));
+        assert_eq!(&i.tape()[..2], &[0, 6]);
+        assert_eq!(i.step_over().unwrap(), Some('>'));
+        assert_eq!(i.step_over().unwrap(), None);
+    }
+
+    #[test]
+    fn step_over_respects_step_limit() {
+        let mut i = InterpreterState::new();
+        i.set_max_steps(Some(10));
+        i.load_string("+[]");
+        i.prepare().unwrap();
+        i.step().unwrap();
+
+        assert!(matches!(
+            i.step_over(),
+            Err(InterpreterError::StepLimitExceeded { limit: 10 })
+        ));
+    }
+
+    #[test]
+    fn pointer_wraps_at_custom_boundary() {
+        let mut i = InterpreterState::with_pointer_wrap(100, CellWidth::Eight, Some(4)).unwrap();
+
+        i.goto_previous_cell().unwrap();
+        assert_eq!(i.pointer(), 3);
+        i.goto_next_cell().unwrap();
+        assert_eq!(i.pointer(), 0);
+        i.move_pointer(9).unwrap();
+        assert_eq!(i.pointer(), 1);
+    }
+
+    #[test]
+    fn pointer_wrap_must_fit_the_tape() {
+        assert!(matches!(
+            InterpreterState::with_pointer_wrap(10, CellWidth::Eight, Some(11)),
+            Err(InterpreterError::InvalidPointerWrap { wrap: 11, tape_size: 10 })
+        ));
+        assert!(InterpreterState::with_pointer_wrap(10, CellWidth::Eight, Some(0)).is_err());
+        assert!(InterpreterState::with_pointer_wrap(10, CellWidth::Eight, Some(10)).is_ok());
+    }
+
+    #[test]
+    fn bang_input_feeds_text_after_bang_to_input() {
+        let path = std::env::temp_dir().join("beefin_bang_input.bf");
+        std::fs::write(&path, ",+.>,+.!HI").unwrap();
+
+        let buffer = CapturedOutput::default();
+        let mut i = InterpreterState::new();
+        i.set_output(Box::new(buffer.clone()));
+        i.set_bang_input(true);
+        i.load_file(path.to_str().unwrap()).unwrap();
+        i.parse().unwrap();
+
+        assert_eq!(*buffer.0.borrow(), b"IJ");
+    }
+
+    #[test]
+    fn timeout_stops_infinite_loop() {
+        let mut i = InterpreterState::new();
+        i.set_timeout(Some(Duration::from_millis(50)));
+
+        let started = Instant::now();
+        let result = i.run_string("+[]");
+
+        assert!(matches!(result, Err(InterpreterError::Timeout { .. })));
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn seek_zero_stops_at_next_zero_cell() {
+        let mut i = InterpreterState::new();
+        i.set_opt_level(OptLevel::Full);
+
+        i.run_string(">+>+>+>>+<<<<[>]").unwrap();
+        assert_eq!(i.pointer(), 4);
+
+        i.run_string("+[<]").unwrap();
+        assert_eq!(i.pointer(), 0);
+    }
+
+    #[test]
+    fn seek_zero_wraps_around_tape() {
+        let mut i = InterpreterState::with_tape_size(10);
+        i.set_opt_level(OptLevel::Full);
+
+        i.run_string("+<+<+[<]").unwrap();
+
+        assert_eq!(i.pointer(), 7);
+    }
+
+    #[test]
+    fn scan_loops_report_the_move_at_tape_end() {
+        let mut i = InterpreterState::with_tape_size(4);
+        i.set_tape_end(TapeEnd::Error);
+        i.set_opt_level(OptLevel::Full);
+
+        assert!(matches!(
+            i.run_string("+>+>+>+<<<[>]"),
+            Err(InterpreterError::PointerOverflow { position: 11, .. })
+        ));
+    }
+
+    #[test]
+    fn seek_zero_without_zero_cells_hits_step_limit() {
+        let mut i = InterpreterState::with_tape_size(3);
+        i.set_max_steps(Some(100));
+        i.set_opt_level(OptLevel::Full);
+
+        assert!(matches!(
+            i.run_string("+>+>+[>]"),
+            Err(InterpreterError::StepLimitExceeded { limit: 100 })
+        ));
+    }
+
+    /// Records the flushed output seen at the moment each byte is read.
+    struct WatchingReader {
+        flushed: CapturedOutput,
+        seen: CapturedOutput,
+    }
+
+    impl Read for WatchingReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let flushed = self.flushed.0.borrow().clone();
+            self.seen.0.borrow_mut().extend(flushed);
+            buf[0] = b'x';
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn output_is_flushed_before_input_and_at_end() {
+        let flushed = CapturedOutput::default();
+        let seen = CapturedOutput::default();
+        let mut i = InterpreterState::new();
+        i.set_output(Box::new(FlushingWriter::new(flushed.clone())));
+        i.set_input(Box::new(WatchingReader { flushed: flushed.clone(), seen: seen.clone() }));
+
+        i.run_string("++++++++[>++++++++<-]>+.+.>,.").unwrap();
+
+        assert_eq!(*seen.0.borrow(), b"AB");
+        assert_eq!(*flushed.0.borrow(), b"ABx");
+    }
+
+    #[test]
+    fn steps_yield_executed_instructions() {
+        let mut i = InterpreterState::new();
+        i.load_string("++>+");
+        i.prepare().unwrap();
+
+        let steps: Vec<ExecutedStep> = i.steps().map(Result::unwrap).collect();
+
+        assert_eq!(
+            steps,
+            [
+                ExecutedStep { op: Op::Add(2), offset: 0, pointer: 0, cell: 2 },
+                ExecutedStep { op: Op::Next, offset: 2, pointer: 1, cell: 0 },
+                ExecutedStep { op: Op::Inc, offset: 3, pointer: 1, cell: 1 },
+            ]
+        );
+    }
+
+    #[test]
+    fn steps_stop_after_first_error() {
+        let mut i = InterpreterState::new();
+        i.set_tape_end(TapeEnd::Error);
+        i.load_string("<+");
+        i.prepare().unwrap();
+
+        let mut steps = i.steps();
+
+        assert!(matches!(steps.next(), Some(Err(InterpreterError::PointerUnderflow { .. }))));
+        assert!(steps.next().is_none());
+    }
+
+    #[test]
+    fn push_input_is_read_before_input_source() {
+        let mut i = InterpreterState::new();
+        i.set_input(Box::new(&b"z"[..]));
+        i.push_input(b"ab");
+        i.push_input(b"c");
+
+        i.run_string(",>,>,>,>,").unwrap();
+
+        assert_eq!(&i.tape()[..6], &[b'a' as u32, b'b' as u32, b'c' as u32, b'z' as u32, 0, 0]);
+    }
+
+    #[test]
+    fn run_bytes_accepts_invalid_utf8_comments() {
+        let buffer = CapturedOutput::default();
+        let mut i = InterpreterState::new();
+        i.set_output(Box::new(buffer.clone()));
+
+        i.run_bytes(b"\xff\xfe comment \xc3(\n++++++++[>++++++++<-]>+.").unwrap();
+
+        assert_eq!(*buffer.0.borrow(), b"A");
+    }
+
+    #[test]
+    fn load_file_accepts_invalid_utf8_comments() {
+        let path = std::env::temp_dir().join("beefin_load_file_invalid_utf8.bf");
+        std::fs::write(&path, b"\x80\x81++++++++[>++++++++<-]>+.\xff").unwrap();
+
+        let buffer = CapturedOutput::default();
+        let mut i = InterpreterState::new();
+        i.set_output(Box::new(buffer.clone()));
+        i.load_file(path.to_str().unwrap()).unwrap();
+        i.parse().unwrap();
+
+        assert_eq!(*buffer.0.borrow(), b"A");
+    }
+
+    #[test]
+    fn watchpoint_fires_only_when_watched_cell_changes() {
+        let mut i = InterpreterState::new();
+        i.add_watchpoint(2);
+        i.load_string("+>+>+-+>+<-");
+        i.prepare().unwrap();
+
+        let mut hits = Vec::new();
+        while i.step().unwrap().is_some() {
+            hits.push(i.take_watch_hit());
+        }
+
+        assert_eq!(
+            hits,
+            [
+                None,
+                None,
+                None,
+                None,
+                Some(WatchHit { cell: 2, old: 0, new: 1, offset: 4 }),
+                None,
+                None,
+                None,
+                Some(WatchHit { cell: 2, old: 1, new: 0, offset: 10 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_reports_watch_hits_to_debug_output() {
+        let buffer = CapturedOutput::default();
+        let mut i = InterpreterState::new();
+        i.set_debug_output(Box::new(buffer.clone()));
+        i.add_watchpoint(1);
+
+        i.run_string("+>++[-]").unwrap();
+
+        assert_eq!(
+            String::from_utf8(buffer.0.take()).unwrap(),
+            "watchpoint: cell 1 changed from 0 to 2 at char 2\n\
+             watchpoint: cell 1 changed from 2 to 0 at char 4\n"
+        );
+    }
+
+    #[test]
+    fn run_file_mapped_matches_load_file() {
+        let path = std::env::temp_dir().join("beefin_run_file_mapped.bf");
+        std::fs::write(&path, HELLO_WORLD).unwrap();
+        let path = path.to_str().unwrap();
+
+        let mapped = CapturedOutput::default();
+        let mut i = InterpreterState::new();
+        i.set_output(Box::new(mapped.clone()));
+        i.run_file_mapped(path).unwrap();
+
+        let loaded = CapturedOutput::default();
+        let mut j = InterpreterState::new();
+        j.set_output(Box::new(loaded.clone()));
+        j.load_file(path).unwrap();
+        j.parse().unwrap();
+
+        assert_eq!(*mapped.0.borrow(), b"Hello World!\n");
+        assert_eq!(*mapped.0.borrow(), *loaded.0.borrow());
+    }
+
+    #[test]
+    fn resume_pauses_at_breakpoints() {
+        let mut i = InterpreterState::new();
+        i.load_string("++>+++<->+");
+        i.add_breakpoint(2);
+        i.add_breakpoint(9);
+        i.prepare().unwrap();
+
+        assert_eq!(i.resume().unwrap(), RunStatus::Paused { offset: 2 });
+        assert_eq!((i.pointer(), i.current_cell()), (0, 2));
+        assert_eq!(i.resume().unwrap(), RunStatus::Paused { offset: 9 });
+        assert_eq!((i.pointer(), i.current_cell()), (1, 3));
+        assert_eq!(i.tape()[0], 1);
+        assert_eq!(i.resume().unwrap(), RunStatus::Finished);
+        assert_eq!(i.current_cell(), 4);
+    }
+
+    #[test]
+    fn parse_ignores_breakpoints() {
+        let mut i = InterpreterState::new();
+        i.load_string("+++");
+        i.add_breakpoint(0);
+        i.parse().unwrap();
+        assert_eq!(i.current_cell(), 3);
+    }
+
+    #[test]
+    fn generate_reproduces_text() {
+        let output = CapturedOutput::default();
+        let mut i = InterpreterState::new();
+        i.set_output(Box::new(output.clone()));
+        i.run_string(&generate("AB")).unwrap();
+        assert_eq!(*output.0.borrow(), b"AB");
+    }
+
+    #[test]
+    fn generate_handles_large_and_downward_deltas() {
+        let text = "Hello, World!\n\u{ff}\u{1}";
+        let output = CapturedOutput::default();
+        let mut i = InterpreterState::new();
+        i.set_output(Box::new(output.clone()));
+        i.run_string(&generate(text)).unwrap();
+        assert_eq!(*output.0.borrow(), text.as_bytes());
+        assert!(generate("A").len() < 65);
+    }
+
+    #[test]
+    fn opt_levels_produce_the_same_output() {
+        let levels = [OptLevel::None, OptLevel::Basic, OptLevel::Full, OptLevel::Aggressive];
+        let outputs: Vec<Vec<u8>> = levels
+            .into_iter()
+            .map(|opt_level| {
+                let output = CapturedOutput::default();
+                let mut i = InterpreterState::new();
+                i.set_opt_level(opt_level);
+                i.set_output(Box::new(output.clone()));
+                i.run_string(HELLO_WORLD).unwrap();
+                let bytes = output.0.borrow().clone();
+                bytes
+            })
+            .collect();
+        assert_eq!(outputs[0], b"Hello World!\n");
+        assert!(outputs.iter().all(|output| *output == outputs[0]));
+    }
+
+    #[test]
+    fn add_at_changes_the_offset_cell_only() {
+        let mut i = InterpreterState::new();
+        i.set_opt_level(OptLevel::Full);
+        i.run_string("+>+<+>>+++<<+").unwrap();
+        assert_eq!(i.pointer(), 0);
+        assert_eq!(&i.tape()[..3], &[3, 1, 3]);
+        assert_eq!(i.profile().moves_right, 3);
+        assert_eq!(i.profile().moves_left, 3);
+    }
+
+    #[test]
+    fn add_at_wraps_and_errors_like_plain_moves() {
+        let mut i = InterpreterState::with_tape_size(4);
+        i.set_opt_level(OptLevel::Full);
+        i.run_string("<+>").unwrap();
+        assert_eq!((i.pointer(), i.tape()[3]), (0, 1));
+
+        let mut i = InterpreterState::with_tape_size(4);
+        i.set_opt_level(OptLevel::Full);
+        i.set_tape_end(TapeEnd::Error);
+        assert!(matches!(
+            i.run_string("<+>"),
+            Err(InterpreterError::PointerUnderflow { position: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn add_at_triggers_watchpoints_on_the_offset_cell() {
+        let mut i = InterpreterState::new();
+        i.set_opt_level(OptLevel::Full);
+        i.add_watchpoint(2);
+        i.load_string(">>++<<");
+        i.prepare().unwrap();
+        i.step().unwrap();
+        assert_eq!(i.take_watch_hit(), Some(WatchHit { cell: 2, old: 0, new: 2, offset: 0 }));
+    }
+
+    #[test]
+    fn cell_modulus_wraps_increments_and_decrements() {
+        let mut i = InterpreterState::new();
+        i.set_cell_modulus(Some(10)).unwrap();
+
+        i.run_string("+++++++++").unwrap();
+        assert_eq!(i.current_cell(), 9);
+        i.run_string("+").unwrap();
+        assert_eq!(i.current_cell(), 0);
+        i.run_string("-").unwrap();
+        assert_eq!(i.current_cell(), 9);
+    }
+
+    #[test]
+    fn cell_modulus_prints_values_modulo_256() {
+        let output = CapturedOutput::default();
+        let mut i = InterpreterState::with_config(10, CellWidth::Sixteen);
+        i.set_cell_modulus(Some(1000)).unwrap();
+        i.set_output(Box::new(output.clone()));
+
+        i.run_string(&format!("{}.", "+".repeat(300))).unwrap();
+
+        assert_eq!(*output.0.borrow(), [44]);
+    }
+
+    #[test]
+    fn cell_modulus_must_fit_the_cell_width() {
+        let mut i = InterpreterState::new();
+        assert!(matches!(
+            i.set_cell_modulus(Some(0)),
+            Err(InterpreterError::InvalidCellModulus { modulus: 0, max: 256 })
+        ));
+        assert!(i.set_cell_modulus(Some(257)).is_err());
+        assert!(i.set_cell_modulus(Some(256)).is_ok());
+        assert!(i.set_cell_modulus(None).is_ok());
+    }
+
+    #[test]
+    fn count_only_suppresses_io_but_keeps_counts() {
+        let output = CapturedOutput::default();
+        let mut i = InterpreterState::new();
+        i.set_count_only(true);
+        i.set_output(Box::new(output.clone()));
+        i.set_input(Box::new(Cursor::new(b"x".to_vec())));
+
+        i.run_string("+++.,>++.").unwrap();
+
+        assert!(output.0.borrow().is_empty());
+        assert_eq!(i.profile().outputs, 2);
+        assert_eq!(i.profile().inputs, 1);
+        assert_eq!(i.profile().increments, 5);
+        assert_eq!(&i.tape()[..2], &[0, 2]);
+    }
+
+    #[test]
+    fn builder_applies_every_setting() {
+        let mut i = InterpreterBuilder::new()
+            .tape_size(4)
+            .cell_width(CellWidth::Sixteen)
+            .eof(EofBehavior::MinusOne)
+            .arithmetic(ArithmeticMode::Saturate)
+            .tape_end(TapeEnd::Grow)
+            .max_steps(100)
+            .build();
+        i.set_input(Box::new(std::io::empty()));
+
+        i.run_string(",+>>>>").unwrap();
+        assert_eq!(i.tape()[0], 65535);
+        assert_eq!(i.tape().len(), 5);
+        assert!(matches!(
+            i.run_string("+[]"),
+            Err(InterpreterError::StepLimitExceeded { limit: 100 })
+        ));
+    }
+
+    #[test]
+    fn builder_defaults_match_new() {
+        let built = InterpreterState::builder().build();
+        let new = InterpreterState::new();
+        assert_eq!(format!("{built:?}"), format!("{new:?}"));
+    }
+
+    #[test]
+    fn group_digits_separates_thousands() {
+        assert_eq!(group_digits(0), "0");
+        assert_eq!(group_digits(999), "999");
+        assert_eq!(group_digits(1000), "1,000");
+        assert_eq!(group_digits(123456), "123,456");
+        assert_eq!(group_digits(1234567), "1,234,567");
+        assert_eq!(group_digits(u64::MAX), "18,446,744,073,709,551,615");
+    }
+
+    #[test]
+    fn profile_reports_steps_per_second() {
+        let profile = Profile { steps: 3000, ..Profile::default() };
+        assert_eq!(profile.steps_per_second(Duration::from_millis(1500)), Some(2000));
+        assert_eq!(profile.steps_per_second(Duration::ZERO), None);
+    }
+
+    #[test]
+    fn poke_then_peek_round_trips() {
+        let mut i = InterpreterState::with_tape_size(4);
+        i.poke(3, 200).unwrap();
+        assert_eq!(i.peek(3), Some(200));
+
+        i.run_string("<").unwrap();
+        assert_eq!(i.pointer(), 3);
+        i.run_string("+").unwrap();
+        assert_eq!(i.peek(3), Some(201));
+    }
+
+    #[test]
+    fn poke_rejects_bad_indices_and_values() {
+        let mut i = InterpreterState::with_tape_size(4);
+        assert_eq!(i.peek(4), None);
+        assert!(matches!(
+            i.poke(4, 1),
+            Err(InterpreterError::CellOutOfBounds { index: 4, tape_size: 4 })
+        ));
+        assert!(matches!(
+            i.poke(0, 256),
+            Err(InterpreterError::CellValueOutOfRange { value: 256, max: 255 })
+        ));
+        assert_eq!(i.peek(0), Some(0));
+    }
+
+    #[test]
+    fn load_appends_files_and_bytes() {
+        let path = std::env::temp_dir().join("beefin_load_program_source.bf");
+        std::fs::write(&path, "#!/usr/bin/env beefin\n++").unwrap();
+
+        let mut i = InterpreterState::new();
+        i.load(ProgramSource::Bytes(b"+>".to_vec())).unwrap();
+        i.load(ProgramSource::File(path)).unwrap();
+        assert!(i.file_content.starts_with(b"+>"));
+        assert!(i.file_content.ends_with(b"\n++"));
+
+        i.parse().unwrap();
+        assert_eq!(&i.tape()[..2], &[1, 2]);
+    }
+
+    #[test]
+    fn load_reports_missing_files() {
+        let mut i = InterpreterState::new();
+        let path = std::path::PathBuf::from("does/not/exist.bf");
+        assert!(matches!(
+            i.load(ProgramSource::File(path.clone())),
+            Err(InterpreterError::FileNotFound(missing)) if missing == path
+        ));
+    }
+
+    #[test]
+    fn interrupt_flag_stops_a_running_program() {
+        let flag = Arc::new(AtomicBool::new(false));
+        let setter = Arc::clone(&flag);
+        let mut i = InterpreterState::new();
+        i.set_interrupt_flag(Some(flag));
+
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            setter.store(true, Ordering::Relaxed);
+        });
+        let result = i.run_string("+++[>+<]");
+        handle.join().unwrap();
+
+        let Err(InterpreterError::Interrupted { steps }) = result else {
+            panic!("expected an interrupt, got {result:?}");
+        };
+        assert_eq!(steps, i.profile().steps);
+        assert!(steps > 4);
+        assert_eq!(i.tape()[0], 3);
+        assert!(i.tape()[1] > 0);
+    }
+
+    #[test]
+    fn interrupt_flag_stops_loops_that_never_end() {
+        let flag = Arc::new(AtomicBool::new(false));
+        let setter = Arc::clone(&flag);
+        let mut i = InterpreterState::with_tape_size(2);
+        i.set_opt_level(OptLevel::Full);
+        i.set_interrupt_flag(Some(flag));
+
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            setter.store(true, Ordering::Relaxed);
+        });
+        // With every cell non-zero, the seek can never find a zero cell.
+        let result = i.run_string("+>+[>]");
+        handle.join().unwrap();
+
+        assert!(matches!(result, Err(InterpreterError::Interrupted { .. })));
+    }
+
+    #[test]
+    fn grow_both_extends_the_tape_in_both_directions() {
+        let mut i = InterpreterState::with_tape_size(2);
+        i.set_tape_end(TapeEnd::GrowBoth);
+
+        i.run_string("+<<<<<++>>>>>>>>>>+++").unwrap();
+        assert_eq!(i.pointer(), 5);
+        assert_eq!(i.peek(-5), Some(2));
+        assert_eq!(i.peek(0), Some(1));
+        assert_eq!(i.peek(5), Some(3));
+        assert_eq!(i.tape()[i.origin()], 1);
+
+        i.poke(-5, 7).unwrap();
+        i.run_string("<<<<<<<<<<").unwrap();
+        assert_eq!((i.pointer(), i.current_cell()), (-5, 7));
+    }
+
+    #[test]
+    fn grow_both_keeps_logical_indices_far_from_the_start() {
+        let mut i = InterpreterState::with_tape_size(1);
+        i.set_tape_end(TapeEnd::GrowBoth);
+
+        let program = format!("{}+{}++", "<".repeat(5000), ">".repeat(10000));
+        i.run_string(&program).unwrap();
+        assert_eq!(i.pointer(), 5000);
+        assert_eq!(i.peek(-5000), Some(1));
+        assert_eq!(i.peek(0), Some(0));
+        assert_eq!(i.peek(5000), Some(2));
+        assert_eq!(i.peek(5001), None);
+        // Growing doubles the tape rather than adding a cell at a time.
+        assert!(i.tape().len() < 20000, "{}", i.tape().len());
+
+        i.poke(-4999, 9).unwrap();
+        let dump = i.dump_tape(-5000, 2);
+        assert!(dump.starts_with("-0001388  01 09 "), "{dump}");
+    }
+
+    #[test]
+    fn grow_both_keeps_watchpoints_on_their_cell() {
+        let mut i = InterpreterState::with_tape_size(1);
+        i.set_tape_end(TapeEnd::GrowBoth);
+        i.add_watchpoint(0);
+        i.load_string("<<>>+");
+        i.prepare().unwrap();
+        while i.step().unwrap().is_some() {}
+        assert_eq!(i.take_watch_hit(), Some(WatchHit { cell: 0, old: 0, new: 1, offset: 4 }));
+    }
+
+    #[test]
+    fn analysis_reports_cells_written_but_never_printed() {
+        let mut i = InterpreterState::new();
+        i.set_output(Box::new(CapturedOutput::default()));
+        i.set_analyze(true);
+
+        i.run_string("+.>>>>>+++<<<<[-]").unwrap();
+
+        assert_eq!(i.write_only_cells(), vec![5]);
+    }
+
+    #[test]
+    fn analysis_is_off_by_default() {
+        let mut i = InterpreterState::new();
+        i.run_string(">+").unwrap();
+        assert!(i.write_only_cells().is_empty());
+    }
+
+    #[test]
+    fn load_file_splices_included_files() {
+        let dir = std::env::temp_dir().join("beefin_include_splice");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("print.bf"), "print the current cell\n.\n").unwrap();
+        let main = dir.join("main.bf");
+        std::fs::write(&main, "++++++++[>++++++++<-]>+\n@include \"print.bf\"\n+.\n").unwrap();
+
+        let output = CapturedOutput::default();
+        let mut i = InterpreterState::new();
+        i.set_output(Box::new(output.clone()));
+        i.load_file(main.to_str().unwrap()).unwrap();
+        i.parse().unwrap();
+
+        assert_eq!(*output.0.borrow(), b"AB");
+    }
+
+    #[test]
+    fn include_errors_point_into_the_included_file() {
+        let dir = std::env::temp_dir().join("beefin_include_error");
+        std::fs::create_dir_all(&dir).unwrap();
+        let helper = dir.join("helper.bf");
+        std::fs::write(&helper, "+\n+]\n").unwrap();
+        let main = dir.join("main.bf");
+        std::fs::write(&main, "+\n@include \"helper.bf\"\n").unwrap();
+
+        let mut i = InterpreterState::new();
+        i.load_file(main.to_str().unwrap()).unwrap();
+
+        match i.validate() {
+            Err(InterpreterError::UnmatchedClose { position: 5, line: 2, column: 2, file }) => {
+                assert_eq!(file, Some(helper));
+            }
+            other => panic!("unexpected result {other:?}"),
+        }
+    }
+
+    #[test]
+    fn runtime_errors_report_line_and_column() {
+        let mut i = InterpreterState::with_tape_size(1);
+        i.set_tape_end(TapeEnd::Error);
+        assert!(matches!(
+            i.run_string("+\n\n  <"),
+            Err(InterpreterError::PointerUnderflow { position: 5, line: 3, column: 3, .. })
+        ));
+
+        let dir = std::env::temp_dir().join("beefin_runtime_error");
+        std::fs::create_dir_all(&dir).unwrap();
+        let helper = dir.join("helper.bf");
+        std::fs::write(&helper, "-\n --\n").unwrap();
+        let main = dir.join("main.bf");
+        std::fs::write(&main, "+\n@include \"helper.bf\"\n").unwrap();
+
+        let mut i = InterpreterState::new();
+        i.set_arithmetic_mode(ArithmeticMode::Error);
+        i.load_file(main.to_str().unwrap()).unwrap();
+
+        match i.parse() {
+            Err(InterpreterError::CellOverflow { line: 2, column: 2, file, .. }) => {
+                assert_eq!(file, Some(helper));
+            }
+            other => panic!("unexpected result {other:?}"),
+        }
+    }
+
+    #[test]
+    fn include_cycles_are_rejected() {
+        let dir = std::env::temp_dir().join("beefin_include_cycle");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.bf"), "@include \"b.bf\"\n").unwrap();
+        std::fs::write(dir.join("b.bf"), "@include \"a.bf\"\n").unwrap();
+
+        let mut i = InterpreterState::new();
+        let result = i.load_file(dir.join("a.bf").to_str().unwrap());
+
+        assert!(matches!(result, Err(InterpreterError::IncludeCycle { .. })));
+    }
+
+    #[test]
+    fn register_moves_a_value_between_cells() {
+        let mut i = InterpreterState::new();
+        i.set_extended(true);
+
+        i.run_string("+++++@>>$<$+").unwrap();
+
+        assert_eq!(&i.tape()[..3], &[5, 6, 5]);
+        assert_eq!(i.register(), 5);
+    }
+
+    #[test]
+    fn register_commands_are_comments_unless_extended() {
+        let mut i = InterpreterState::new();
+        i.run_string("+++@>$").unwrap();
+        assert_eq!(&i.tape()[..2], &[3, 0]);
+    }
+
+    #[test]
+    fn try_run_string_collects_output_and_state() {
+        let mut i = InterpreterState::new();
+
+        let result = i.try_run_string(",+.>++", b"A").unwrap();
+
+        assert_eq!(
+            result,
+            RunResult { output: b"B".to_vec(), tape_snapshot: vec![66, 2], pointer: 1, steps: 6 }
+        );
+    }
+
+    #[test]
+    fn crlf_output_translates_newlines() {
+        let output = CapturedOutput::default();
+        let mut i = InterpreterState::new();
+        i.set_output(Box::new(output.clone()));
+        i.set_crlf_output(true);
+
+        i.run_string("++++++++++.>+++++++++++++.").unwrap();
+
+        assert_eq!(*output.0.borrow(), b"\r\n\r");
+    }
+
+    #[test]
+    fn step_back_restores_tape_and_pointer() {
+        let mut i = InterpreterState::new();
+        i.set_history_limit(16);
+        i.load_string("++>+++[-<+>]<.");
+        i.prepare().unwrap();
+        for _ in 0..5 {
+            i.step().unwrap();
+        }
+        let tape = i.tape()[..4].to_vec();
+        let pointer = i.pointer();
+
+        for _ in 0..4 {
+            i.step().unwrap();
+        }
+        for _ in 0..4 {
+            assert!(i.step_back());
+        }
+
+        assert_eq!(&i.tape()[..4], tape.as_slice());
+        assert_eq!(i.pointer(), pointer);
+        assert_eq!(i.step().unwrap(), Some('<'));
+    }
+
+    #[test]
+    fn step_back_undoes_moves_that_grew_the_tape_left() {
+        let mut i = InterpreterState::with_tape_size(2);
+        i.set_tape_end(TapeEnd::GrowBoth);
+        i.set_history_limit(16);
+        i.load_string("+<+");
+        i.prepare().unwrap();
+        for _ in 0..3 {
+            i.step().unwrap();
+        }
+        assert_eq!((i.pointer(), i.peek(-1), i.peek(0)), (-1, Some(1), Some(1)));
+
+        assert!(i.step_back());
+        assert!(i.step_back());
+        assert_eq!((i.pointer(), i.peek(-1), i.peek(0)), (0, Some(0), Some(1)));
+        assert!(i.step_back());
+        assert_eq!(i.peek(0), Some(0));
+        assert!(!i.step_back());
+    }
+
+    #[test]
+    fn step_back_history_is_bounded() {
+        let mut i = InterpreterState::new();
+        i.set_history_limit(2);
+        i.load_string("+>+>+");
+        i.prepare().unwrap();
+        for _ in 0..5 {
+            i.step().unwrap();
+        }
+
+        assert!(i.step_back());
+        assert!(i.step_back());
+        assert!(!i.step_back());
+        assert_eq!(&i.tape()[..3], &[1, 1, 0]);
+        assert_eq!(i.pointer(), 1);
+    }
+
+    #[test]
+    fn dialects_preset_the_matching_options() {
+        let classic = InterpreterBuilder::new()
+            .tape_size(30000)
+            .cell_width(CellWidth::Eight)
+            .eof(EofBehavior::Zero)
+            .arithmetic(ArithmeticMode::Wrap)
+            .tape_end(TapeEnd::Wrap);
+        assert_eq!(InterpreterBuilder::new().dialect(Dialect::Classic), classic);
+        assert_eq!(
+            InterpreterBuilder::new().dialect(Dialect::Pbrain),
+            classic.clone().procedures(true)
+        );
+        assert_eq!(
+            InterpreterBuilder::new().dialect(Dialect::Urban),
+            classic.eof(EofBehavior::Unchanged)
+        );
+        assert_eq!(
+            InterpreterBuilder::new().tape_size(10).dialect(Dialect::Classic).tape_size(20),
+            InterpreterBuilder::new().dialect(Dialect::Classic).tape_size(20)
+        );
+    }
+
+    #[test]
+    fn dialect_parses_known_names_only() {
+        assert_eq!("urban".parse(), Ok(Dialect::Urban));
+        let err = "brainfork".parse::<Dialect>().unwrap_err();
+        assert!(err.contains("classic, urban, pbrain"), "{err}");
+    }
+
+    #[test]
+    fn procedure_increments_a_cell_each_call() {
+        let mut i = InterpreterState::new();
+        i.set_procedures(true);
+
+        i.run_string(":>+<:??").unwrap();
+
+        assert_eq!(&i.tape()[..2], &[0, 2]);
+    }
+
+    #[test]
+    fn step_back_undoes_calls_returns_and_definitions() {
+        let mut i = InterpreterState::new();
+        i.set_procedures(true);
+        i.set_history_limit(100);
+        i.load_string(":>+<:??");
+        i.prepare().unwrap();
+
+        i.step().unwrap();
+        assert_eq!(i.procedures.get(&0), Some(&0));
+        assert!(i.step_back());
+        assert!(i.procedures.is_empty());
+
+        // Into the first call, then back out over the `?`.
+        i.step().unwrap();
+        i.step().unwrap();
+        assert_eq!(i.call_stack, [5]);
+        assert!(i.step_back());
+        assert!(i.call_stack.is_empty());
+
+        // Through the closing `:`, then back into the procedure.
+        for _ in 0..5 {
+            i.step().unwrap();
+        }
+        assert!(i.call_stack.is_empty());
+        assert!(i.step_back());
+        assert_eq!((i.call_stack.as_slice(), i.instruction_pointer), (&[5][..], 4));
+
+        while i.step().unwrap().is_some() {}
+        assert_eq!(&i.tape()[..2], &[0, 2]);
+    }
+
+    #[test]
+    fn procedures_can_recurse() {
+        let mut i = InterpreterState::new();
+        i.set_procedures(true);
+
+        // Procedure 0 moves three from cell 1 to cell 2, one per nested call.
+        i.run_string(">+++<:>[->+<<?>]<:>[<?>]").unwrap();
+
+        assert_eq!(&i.tape()[..3], &[0, 0, 3]);
+    }
+
+    #[test]
+    fn procedure_errors() {
+        let mut i = InterpreterState::new();
+        i.set_procedures(true);
+
+        assert!(matches!(
+            i.run_string("+?"),
+            Err(InterpreterError::UndefinedProcedure { id: 1, position: 1, .. })
+        ));
+        assert!(matches!(
+            i.run_string(":?:?"),
+            Err(InterpreterError::CallDepthExceeded { limit: MAX_CALL_DEPTH, .. })
+        ));
+        assert!(matches!(
+            i.run_string("[:]:"),
+            Err(InterpreterError::UnmatchedProcedure { position: 2, command: ']', .. })
+        ));
+        assert!(matches!(
+            i.run_string(":+"),
+            Err(InterpreterError::UnmatchedProcedure { position: 0, command: ':', .. })
+        ));
+        assert!(matches!(
+            i.run_string(":[:]"),
+            Err(InterpreterError::UnmatchedProcedure { position: 2, command: ':', .. })
+        ));
+    }
+
+    #[test]
+    fn unmatched_procedures_in_included_files_name_the_file() {
+        let dir = std::env::temp_dir().join("beefin_procedure_include");
+        std::fs::create_dir_all(&dir).unwrap();
+        let helper = dir.join("helper.bf");
+        std::fs::write(&helper, "+\n  :+\n").unwrap();
+        let main = dir.join("main.bf");
+        std::fs::write(&main, "+\n@include \"helper.bf\"\n").unwrap();
+
+        let mut i = InterpreterState::new();
+        i.set_procedures(true);
+        i.load_file(main.to_str().unwrap()).unwrap();
+
+        let err = i.prepare().unwrap_err();
+        let location = format!("error in {} at line 2, column 3: ", helper.display());
+        assert_eq!(err.to_string(), format!("{location}unmatched ':'"));
+    }
+
+    #[test]
+    fn procedure_commands_are_comments_by_default() {
+        let mut i = InterpreterState::new();
+        i.run_string(":+:?").unwrap();
+        assert_eq!(i.tape()[0], 1);
+    }
+
+    #[test]
+    fn parentheses_are_comments_in_pbrain() {
+        let mut i = InterpreterBuilder::new().dialect(Dialect::Pbrain).build();
+        i.run_string("(+)").unwrap();
+        assert_eq!(i.tape()[0], 1);
+    }
+
+    #[test]
+    fn exported_state_resumes_in_a_fresh_interpreter() {
+        let program = "++++[>++++++++<-]>+.>+++[<.>-]";
+        let output = CapturedOutput::default();
+        let mut i = InterpreterState::new();
+        i.set_output(Box::new(output.clone()));
+        i.load_string(program);
+        i.prepare().unwrap();
+        i.add_breakpoint(program.find('.').unwrap());
+        assert_eq!(i.resume().unwrap(), RunStatus::Paused { offset: 19 });
+        let state = i.export_state();
+
+        let resumed_output = CapturedOutput::default();
+        let mut resumed = InterpreterState::new();
+        resumed.set_output(Box::new(resumed_output.clone()));
+        resumed.load_string(program);
+        resumed.prepare().unwrap();
+        resumed.import_state(&state).unwrap();
+        assert_eq!(resumed.export_state(), state);
+
+        assert_eq!(i.resume().unwrap(), RunStatus::Finished);
+        assert_eq!(resumed.resume().unwrap(), RunStatus::Finished);
+        assert_eq!(resumed.tape(), i.tape());
+        assert_eq!(resumed.pointer(), i.pointer());
+        assert_eq!(resumed.profile().steps, i.profile().steps);
+        assert_eq!(*resumed_output.0.borrow(), b"!!!!");
+    }
+
+    #[test]
+    fn exported_state_resumes_inside_a_procedure() {
+        // Tape 1 only gets its 3 back from the register, and the snapshot is
+        // taken inside the first call of procedure 0.
+        let program = "}+++@[-]{:>+<:??}$";
+        let configure = |i: &mut InterpreterState| {
+            i.set_procedures(true);
+            i.set_extended(true);
+            i.set_tape_count(2);
+            i.load_string(program);
+            i.prepare().unwrap();
+        };
+        let mut i = InterpreterState::new();
+        configure(&mut i);
+        i.add_breakpoint(program.find(">+<").unwrap() + 1);
+        assert!(matches!(i.resume().unwrap(), RunStatus::Paused { .. }));
+        let state = i.export_state();
+
+        let mut resumed = InterpreterState::new();
+        configure(&mut resumed);
+        resumed.import_state(&state).unwrap();
+        assert_eq!(resumed.export_state(), state);
+
+        assert_eq!(resumed.resume().unwrap(), RunStatus::Finished);
+        assert_eq!((resumed.active_tape(), resumed.tape()[0]), (1, 3));
+        while i.resume().unwrap() != RunStatus::Finished {}
+        assert_eq!(resumed.export_state(), i.export_state());
+    }
+
+    #[test]
+    fn import_state_rejects_bad_snapshots() {
+        let mut i = InterpreterState::with_tape_size(4);
+        i.set_procedures(true);
+        i.load_string(":+:?");
+        i.prepare().unwrap();
+        let valid: Value = serde_json::from_str(&i.export_state()).unwrap();
+        let tape = json!({"tape_size": 4, "tape": [], "pointer": 0, "origin": 0});
+        assert!(matches!(i.import_state("not json"), Err(InterpreterError::InvalidState { .. })));
+        for (field, value) in [
+            ("tape", json!([256])),
+            ("pointer", json!(4)),
+            ("instruction_pointer", json!(5)),
+            ("tape_size", json!(u64::MAX)),
+            ("register", json!(256)),
+            ("active_tape", json!(1)),
+            ("other_tapes", json!([tape])),
+            ("procedures", json!([[0, 1]])),
+            ("call_stack", json!([0])),
+            ("steps", json!(-1)),
+        ] {
+            let mut state = valid.clone();
+            state[field] = value;
+            let result = i.import_state(&state.to_string());
+            assert!(matches!(result, Err(InterpreterError::InvalidState { .. })), "{field}");
+        }
+        i.import_state(&valid.to_string()).unwrap();
+    }
+
+    #[test]
+    fn run_batch_returns_results_in_input_order() {
+        let dir = std::env::temp_dir().join("beefin_run_batch");
+        std::fs::create_dir_all(&dir).unwrap();
+        let programs = ["+++.", ">++++++++[<++++++++>-]<+.", "[", "+>+>+"];
+        let mut paths: Vec<PathBuf> = programs
+            .iter()
+            .enumerate()
+            .map(|(index, program)| {
+                let path = dir.join(format!("{index}.bf"));
+                std::fs::write(&path, program).unwrap();
+                path
+            })
+            .collect();
+        paths.push(dir.join("missing.bf"));
+
+        let results = run_batch(&paths);
+
+        assert_eq!(results.len(), 5);
+        let first = results[0].as_ref().unwrap();
+        assert_eq!((first.output.as_slice(), first.steps), (&[3][..], 4));
+        assert_eq!(results[1].as_ref().unwrap().output, b"A");
+        assert!(matches!(results[2], Err(InterpreterError::UnmatchedOpen { position: 0, .. })));
+        let last = results[3].as_ref().unwrap();
+        assert_eq!((last.tape_snapshot.as_slice(), last.pointer), (&[1, 1, 1][..], 2));
+        assert!(matches!(results[4], Err(InterpreterError::FileNotFound(_))));
+    }
+
+    #[test]
+    fn view_offset_moves_the_window_but_not_the_pointer() {
+        let mut i = InterpreterState::new();
+        i.run_string("+>>>>>>>>>>++").unwrap();
+
+        i.set_view_offset(Some(1));
+        assert_eq!(i.render_tape(1, false), "    0:  1  0  0 ");
+        assert_eq!(i.pointer(), 10);
+
+        i.set_view_offset(None);
+        assert_eq!(i.render_tape(1, false), "    9:  0 [2] 0 ");
+    }
+
+    #[test]
+    fn aggressive_keeps_output_when_removing_dead_loops() {
+        let program = ">++++++++[<++++++++>-][the loop left this cell zero -.]<+.+.";
+        let run = |opt_level| {
+            let output = CapturedOutput::default();
+            let mut i = InterpreterState::new();
+            i.set_opt_level(opt_level);
+            i.set_output(Box::new(output.clone()));
+            i.run_string(program).unwrap();
+            let ops = i.instructions().len();
+            let bytes = output.0.take();
+            (bytes, ops)
+        };
+
+        let (full, full_ops) = run(OptLevel::Full);
+        let (aggressive, aggressive_ops) = run(OptLevel::Aggressive);
+        assert_eq!(aggressive, b"AB");
+        assert_eq!(aggressive, full);
+        assert_eq!(aggressive_ops, full_ops - 4);
+    }
+
+    #[test]
+    fn source_map_gives_offset_line_and_column_of_every_op() {
+        let mut i = InterpreterState::new();
+        i.load_string("++ add\n[-]\n  é>.");
+        i.prepare().unwrap();
+
+        assert_eq!(
+            i.source_map(),
+            serde_json::json!([
+                { "op": 0, "offset": 0, "line": 1, "column": 1 },
+                { "op": 1, "offset": 7, "line": 2, "column": 1 },
+                { "op": 2, "offset": 15, "line": 3, "column": 4 },
+                { "op": 3, "offset": 16, "line": 3, "column": 5 },
+            ])
+        );
+    }
+
+    #[test]
+    fn numeric_io_prints_and_reads_decimal_numbers() {
+        let output = CapturedOutput::default();
+        let mut i = InterpreterState::new();
+        i.set_numeric_io(true);
+        i.set_output(Box::new(output.clone()));
+        i.set_input(Box::new(Cursor::new(b"  42 \n-1 300".to_vec())));
+
+        i.run_string(&format!("{}.>,>,>,>,", "+".repeat(200))).unwrap();
+
+        assert_eq!(*output.0.borrow(), b"200 ");
+        assert_eq!(&i.tape()[..5], &[200, 42, 255, 44, 0]);
+    }
+
+    #[test]
+    fn numeric_io_rejects_non_numbers() {
+        let mut i = InterpreterState::new();
+        i.set_numeric_io(true);
+        i.set_input(Box::new(Cursor::new(b"4x2".to_vec())));
+
+        let result = i.run_string(",");
+
+        assert!(matches!(result, Err(InterpreterError::InvalidNumber { input }) if input == "4x2"));
+    }
+
+    #[test]
+    fn minimize_shrinks_while_keeping_the_output() {
+        let padded = "Prints A: ++--<>><  ++++++++[>++++++++<-]>+. [-]+-[-] >><<,";
+
+        let minimized = minimize(padded, b"x");
+
+        assert!(minimized.len() < 25, "{minimized}");
+        let output = CapturedOutput::default();
+        let mut i = InterpreterState::new();
+        i.set_output(Box::new(output.clone()));
+        i.run_string(&minimized).unwrap();
+        assert_eq!(*output.0.borrow(), b"A");
+    }
+
+    #[test]
+    fn minimize_keeps_the_kind_of_failure() {
+        let minimized = minimize("spins: ++ +[>+<] never ends", b"");
+        assert_eq!(minimized, "+[]");
+    }
+
+    #[test]
+    fn cat_loop_copies_large_input() {
+        let input: Vec<u8> = (0..1_000_000).map(|n| (n % 255 + 1) as u8).collect();
+        let output = CapturedOutput::default();
+        let mut i = InterpreterState::new();
+        i.set_input(Box::new(Cursor::new(input.clone())));
+        i.set_output(Box::new(output.clone()));
+
+        i.run_string(",[.,]").unwrap();
+
+        assert_eq!(*output.0.borrow(), input);
+        assert_eq!(i.profile().steps, 2 + 3 * input.len() as u64);
+        assert_eq!(i.profile().inputs, input.len() as u64 + 1);
+    }
+
+    #[test]
+    fn cat_loop_stops_at_a_zero_byte() {
+        let output = CapturedOutput::default();
+        let mut i = InterpreterState::new();
+        i.set_input(Box::new(Cursor::new(b"ab\0cd".to_vec())));
+        i.set_output(Box::new(output.clone()));
+
+        i.run_string(",[.,],.").unwrap();
+
+        assert_eq!(*output.0.borrow(), b"abc");
+    }
+
+    #[test]
+    fn cat_loop_keeps_running_on_non_zero_eof() {
+        let output = CapturedOutput::default();
+        let mut i = InterpreterState::new();
+        i.set_eof_behavior(EofBehavior::Unchanged);
+        i.set_max_steps(Some(20));
+        i.set_input(Box::new(Cursor::new(b"ab".to_vec())));
+        i.set_output(Box::new(output.clone()));
+
+        let err = i.run_string(",[.,]").unwrap_err();
+
+        assert!(matches!(err, InterpreterError::StepLimitExceeded { limit: 20 }));
+        assert_eq!(*output.0.borrow(), b"abbbbb");
+        assert_eq!(i.profile().steps, 20);
+    }
+
+    #[test]
+    fn tapes_keep_their_own_cells_and_pointers() {
+        let mut i = InterpreterState::new();
+        i.set_tape_count(2);
+
+        i.run_string("+++>+}++++++++{").unwrap();
+        assert_eq!(i.active_tape(), 0);
+        assert_eq!(&i.tape()[..2], &[3, 1]);
+        assert_eq!(i.pointer(), 1);
+
+        i.run_string("}").unwrap();
+        assert_eq!(i.active_tape(), 1);
+        assert_eq!(&i.tape()[..2], &[8, 0]);
+        assert_eq!(i.pointer(), 0);
+
+        i.run_string("}").unwrap();
+        assert_eq!(i.active_tape(), 0);
+    }
+
+    #[test]
+    fn tape_commands_are_comments_on_a_single_tape() {
+        let mut i = InterpreterState::new();
+        i.run_string("++}+{+").unwrap();
+        assert_eq!(i.tape()[0], 4);
+        assert_eq!(i.active_tape(), 0);
+    }
+
+    #[test]
+    fn strict_ascii_rejects_the_first_disallowed_byte() {
+        let mut i = InterpreterState::new();
+        i.set_strict_ascii(Some(b"ab"));
+
+        i.run_string("+ a\n+ b").unwrap();
+        let err = i.run_string("+ab\n+c+").unwrap_err();
+
+        assert!(matches!(
+            err,
+            InterpreterError::DisallowedByte { position: 5, line: 2, column: 2, byte: b'c', .. }
+        ));
+    }
+
+    #[test]
+    fn strict_ascii_names_the_included_file_of_a_disallowed_byte() {
+        let dir = std::env::temp_dir().join("beefin_strict_ascii_include");
+        std::fs::create_dir_all(&dir).unwrap();
+        let helper = dir.join("helper.bf");
+        std::fs::write(&helper, "+\n +c\n").unwrap();
+        let main = dir.join("main.bf");
+        std::fs::write(&main, "+\n@include \"helper.bf\"\n").unwrap();
+
+        let mut i = InterpreterState::new();
+        i.set_strict_ascii(Some(b""));
+        i.load_file(main.to_str().unwrap()).unwrap();
+
+        match i.prepare() {
+            Err(InterpreterError::DisallowedByte { line: 2, column: 3, file, byte: b'c', .. }) => {
+                assert_eq!(file, Some(helper));
+            }
+            other => panic!("unexpected result {other:?}"),
+        }
+    }
+
+    #[test]
+    fn strict_ascii_allows_enabled_commands_only() {
+        let mut i = InterpreterState::new();
+        i.set_strict_ascii(Some(b""));
+        assert!(i.run_string("+#").is_err());
+
+        i.set_debug_command(true);
+        i.set_debug_output(Box::new(std::io::sink()));
+        i.run_string("+#").unwrap();
+    }
+
+    #[test]
+    fn seeded_input_repeats_for_the_same_seed() {
+        let run = |seed| {
+            let output = CapturedOutput::default();
+            let mut i = InterpreterState::new();
+            i.set_input(Box::new(SeededInput::new(seed)));
+            i.set_output(Box::new(output.clone()));
+            i.run_string(",.,.,.,.,.,.,.,.").unwrap();
+            let bytes = output.0.borrow().clone();
+            bytes
+        };
+
+        assert_eq!(run(1), run(1));
+        assert_ne!(run(1), run(2));
+    }
+}
@@ -1,238 +1,638 @@
 use std::env;
 use std::fs::File;
-use std::io::Read;
-use std::io::stdin;
-use std::path::Path;
+use std::io::{stderr, stdin, stdout, BufReader, BufWriter, IsTerminal, Write};
 use std::process::exit;
-use log::{Level, log};
-use std::str;
-
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() == 1 {
-            eprint!("You have to supply pathname to .bf file");
-            exit(1);
-    }
-    let filepath = &args[1];
-
-    let mut interpreter: InterpreterState = Interpreter::new();
-    interpreter.load_file(filepath);
-    interpreter.parse(None);
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use beefin::{
+    find_infinite_loops, group_digits, ArithmeticMode, AsciiWriter, BufferedOutput, Dialect,
+    Interpreter, InterpreterBuilder, InterpreterError, InterpreterState, OptLevel,
+    OutputBuffering, OutputMode, ProgramSource, RunStatus, SeededInput, TapeEnd, Utf8Writer,
+    WavWriter, DEFAULT_TAPE_SIZE,
+};
+use log::LevelFilter;
+use serde_json::json;
+
+/// How many steps the `--debug` stepper can undo.
+const DEBUG_HISTORY_LIMIT: usize = 10_000;
+
+#[derive(Debug, PartialEq)]
+enum Mode {
+    Run,
+    Repl,
+    Debug,
+    EmitC,
+    DumpOps,
+    SourceMap,
+    Version,
+    Capabilities,
 }
 
 #[derive(Debug)]
-struct InterpreterState {
-    cells: [u8; 30000],
-    cell_index: usize,
-    loops_opened: usize,
-    file_content: String,
-    loops_data: Vec<[usize; 2]>,
-}
-
-
-trait Interpreter {
-    fn new() -> Self;
-    fn load_file(&mut self, filename: &str);
-    fn parse(&mut self, items: Option<&String>);
-    fn execute_loop_context(&mut self);
-
-    // Language operations
-    fn increment(&mut self);
-    fn decrement(&mut self);
-    fn goto_next_cell(&mut self);
-    fn goto_previous_cell(&mut self);
-    fn open_loop(&mut self, current_parser_index: usize);
-    fn close_loop(&mut self, current_parser_index: usize);
-    fn print(&self);
-    fn input(&mut self);
+struct Options {
+    mode: Mode,
+    filepaths: Vec<String>,
+    profile: bool,
+    profile_json: bool,
+    signed: bool,
+    /// How many non-zero cells `--dump` prints, if it is given.
+    dump: Option<usize>,
+    max_steps: Option<u64>,
+    timeout: Option<Duration>,
+    tape_end: Option<TapeEnd>,
+    debug_command: bool,
+    extended: bool,
+    tapes: usize,
+    debug_window: usize,
+    trace: Option<String>,
+    input_file: Option<String>,
+    seed: Option<u64>,
+    program_stdin: bool,
+    bang_input: bool,
+    output_mode: OutputMode,
+    output_buffering: OutputBuffering,
+    placeholder: u8,
+    crlf: bool,
+    numeric_io: bool,
+    emit_pcm: Option<String>,
+    sample_rate: u32,
+    lint: bool,
+    strict_ascii: bool,
+    allowed_comments: String,
+    count_only: bool,
+    analyze: bool,
+    mmap: bool,
+    log_level: Option<LevelFilter>,
+    watchpoints: Vec<isize>,
+    arithmetic: Option<ArithmeticMode>,
+    dialect: Option<Dialect>,
+    opt_level: OptLevel,
+    cell_modulus: Option<u32>,
 }
 
-
-impl Interpreter for InterpreterState {
-    fn new() -> Self {
-        return Self {
-            cell_index: 0,
-            cells: [0; 30000],
-            file_content: String::new(),
-            loops_opened: 0,
-            loops_data: Vec::new(),
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        mode: Mode::Run,
+        filepaths: Vec::new(),
+        profile: false,
+        profile_json: false,
+        signed: false,
+        dump: None,
+        max_steps: None,
+        timeout: None,
+        tape_end: None,
+        debug_command: false,
+        extended: false,
+        tapes: 1,
+        debug_window: 8,
+        trace: None,
+        input_file: None,
+        seed: None,
+        program_stdin: false,
+        bang_input: false,
+        output_mode: OutputMode::Raw,
+        output_buffering: OutputBuffering::Line,
+        placeholder: b'?',
+        crlf: false,
+        numeric_io: false,
+        emit_pcm: None,
+        sample_rate: 8000,
+        lint: false,
+        strict_ascii: false,
+        allowed_comments: String::new(),
+        count_only: false,
+        analyze: false,
+        mmap: false,
+        log_level: None,
+        watchpoints: Vec::new(),
+        arithmetic: None,
+        dialect: None,
+        opt_level: OptLevel::Basic,
+        cell_modulus: None,
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_string())),
+            _ => (arg.as_str(), None),
         };
-    }
-
-    fn load_file(&mut self, input_filename: &str) {
-        let path = Path::new(input_filename);
-        if !path.exists() {
-            panic!("File {path:?} does not exist");
-        }
-        let mut file = match File::open(path) {
-            Err(_) => panic!("could not read file"),
-            Ok(content) => content,
+        let mut value = |flag: &str| {
+            inline_value
+                .clone()
+                .or_else(|| args.next().cloned())
+                .ok_or(format!("{flag} requires a value"))
         };
 
-        file.read_to_string(&mut self.file_content)
-            .expect("Could not read to buffer");
-    }
-
-    fn parse(&mut self, items: Option<&String>) {
-        let chars: Vec<char> = match items {
-            Some(val) => val.chars().collect(),
-            None => self.file_content.chars().collect(),
-        };
-
-        for (index, char) in chars.iter().enumerate() {
-            match char {
-                '+' => self.increment(),
-                '-' => self.decrement(),
-                '>' => self.goto_next_cell(),
-                '<' => self.goto_previous_cell(),
-                '.' => self.print(),
-                '[' => self.open_loop(index),
-                ']' => self.close_loop(index),
-                ',' => self.input(),
-                _ => log!(Level::Debug, "Passed other char, treating as comment"),
+        match flag {
+            "--repl" => options.mode = Mode::Repl,
+            "--debug" => options.mode = Mode::Debug,
+            "--emit-c" => options.mode = Mode::EmitC,
+            "--dump-ops" => options.mode = Mode::DumpOps,
+            "--source-map" => options.mode = Mode::SourceMap,
+            "--version" => options.mode = Mode::Version,
+            "--capabilities" => options.mode = Mode::Capabilities,
+            "--profile" => options.profile = true,
+            "--profile-json" => options.profile_json = true,
+            "--signed" => options.signed = true,
+            "--dump" => {
+                // The count is only taken inline, so `--dump program.bf`
+                // still runs the program.
+                let count = match &inline_value {
+                    Some(count) => count
+                        .parse()
+                        .map_err(|_| format!("Invalid value for --dump: {count}"))?,
+                    None => usize::MAX,
+                };
+                options.dump = Some(count);
+            }
+            "--debug-command" => options.debug_command = true,
+            "--extended" => options.extended = true,
+            "--program-stdin" => options.program_stdin = true,
+            "--with-bang-input" => options.bang_input = true,
+            "--utf8" => options.output_mode = OutputMode::Utf8,
+            "--output-mode" => options.output_mode = value(flag)?.parse()?,
+            "--output-buffering" => options.output_buffering = value(flag)?.parse()?,
+            "--placeholder" => {
+                let value = value(flag)?;
+                options.placeholder = match value.as_bytes() {
+                    &[byte] if byte.is_ascii() => byte,
+                    _ => return Err(format!("Invalid value for --placeholder: {value}")),
+                };
+            }
+            "--crlf" => options.crlf = true,
+            "--numeric-io" => options.numeric_io = true,
+            "--lint" => options.lint = true,
+            "--strict-ascii" => options.strict_ascii = true,
+            "--allow-comments" => options.allowed_comments = value(flag)?,
+            "--mmap" => options.mmap = true,
+            "--analyze" => options.analyze = true,
+            "--count-only" => {
+                options.count_only = true;
+                options.profile = true;
+                options.dump = Some(usize::MAX);
+            }
+            "--quiet" => options.log_level = Some(LevelFilter::Error),
+            "--verbose" => options.log_level = Some(LevelFilter::Debug),
+            "--max-steps" => {
+                let value = value(flag)?;
+                let max_steps = value
+                    .parse()
+                    .map_err(|_| format!("Invalid value for --max-steps: {value}"))?;
+                options.max_steps = Some(max_steps);
+            }
+            "--timeout" => {
+                let value = value(flag)?;
+                let millis = value
+                    .parse()
+                    .map_err(|_| format!("Invalid value for --timeout: {value}"))?;
+                options.timeout = Some(Duration::from_millis(millis));
+            }
+            "--watch" => {
+                let value = value(flag)?;
+                let cell = value
+                    .parse()
+                    .map_err(|_| format!("Invalid value for --watch: {value}"))?;
+                options.watchpoints.push(cell);
+            }
+            "--cell-modulus" => {
+                let value = value(flag)?;
+                let modulus = value
+                    .parse()
+                    .map_err(|_| format!("Invalid value for --cell-modulus: {value}"))?;
+                options.cell_modulus = Some(modulus);
+            }
+            "--tape-end" => options.tape_end = Some(value(flag)?.parse()?),
+            "--arithmetic" => options.arithmetic = Some(value(flag)?.parse()?),
+            "--dialect" => options.dialect = Some(value(flag)?.parse()?),
+            "--optimize" => options.opt_level = value(flag)?.parse()?,
+            "--trace" => options.trace = Some(value(flag)?),
+            "--input-file" => options.input_file = Some(value(flag)?),
+            "--seed" => {
+                let value = value(flag)?;
+                let seed = value.parse().map_err(|_| format!("Invalid value for --seed: {value}"))?;
+                options.seed = Some(seed);
             }
+            "--emit-pcm" => options.emit_pcm = Some(value(flag)?),
+            "--sample-rate" => {
+                let value = value(flag)?;
+                options.sample_rate = value
+                    .parse()
+                    .map_err(|_| format!("Invalid value for --sample-rate: {value}"))?;
+            }
+            "--tapes" => {
+                let value = value(flag)?;
+                options.tapes = value
+                    .parse()
+                    .ok()
+                    .filter(|&tapes| tapes > 0)
+                    .ok_or_else(|| format!("Invalid value for --tapes: {value}"))?;
+            }
+            "--debug-window" => {
+                let value = value(flag)?;
+                options.debug_window = value
+                    .parse()
+                    .map_err(|_| format!("Invalid value for --debug-window: {value}"))?;
+            }
+            flag if flag.starts_with("--") => return Err(format!("Unknown option {flag}")),
+            path => options.filepaths.push(path.to_string()),
         }
     }
 
-    fn execute_loop_context(&mut self) {
-        let loop_data = self.loops_data[self.loops_opened - 1];
-        if loop_data[1] == 0 {
-            panic!("Missing enclosing ']' near char nr")
+    let needs_program = matches!(
+        options.mode,
+        Mode::Run | Mode::Debug | Mode::EmitC | Mode::DumpOps | Mode::SourceMap
+    );
+    if options.filepaths.is_empty() && needs_program && !options.program_stdin {
+        if stdin().is_terminal() {
+            return Err(String::from("You have to supply pathname to .bf file"));
         }
-        while self.cells[self.cell_index] > 0 {
-            let slice = &self.file_content[
-                loop_data[0]..=loop_data[1]
-                ].to_string();
-            self.parse(Some(slice))
-        }
-
-        self.loops_data.clear();
+        options.filepaths.push(String::from("-"));
     }
+    if options.mmap && (options.filepaths.len() != 1 || options.filepaths[0] == "-") {
+        return Err(String::from("--mmap needs exactly one program file"));
+    }
+    Ok(options)
+}
 
-
-    fn increment(&mut self) {
-        if self.cells[self.cell_index] == 255 {
-            self.cells[self.cell_index] = 0;
-        } else {
-            self.cells[self.cell_index] += 1;
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{message}");
+            exit(1);
         }
+    };
 
+    let mut logger = env_logger::Builder::from_default_env();
+    if let Some(level) = options.log_level {
+        logger.filter_level(level);
     }
+    logger.init();
 
-    fn decrement(&mut self) {
-        if self.cells[self.cell_index] == 0 {
-            self.cells[self.cell_index] = 255;
-        } else {
-            self.cells[self.cell_index] -= 1;
+    match options.mode {
+        Mode::Version => {
+            println!("beefin {}", env!("CARGO_PKG_VERSION"));
+            return;
+        }
+        Mode::Capabilities => {
+            println!("{}", capabilities());
+            return;
         }
+        _ => {}
     }
 
-    fn goto_next_cell(&mut self) {
-        if self.cell_index == 29999 {
-            self.cell_index = 0;
-        } else {
-            self.cell_index += 1;
+    let mut interpreter = match options.dialect {
+        Some(dialect) => InterpreterBuilder::new().dialect(dialect).build(),
+        None => InterpreterState::new(),
+    };
+    let interrupted = Arc::new(AtomicBool::new(false));
+    if matches!(options.mode, Mode::Run | Mode::Debug) {
+        let flag = Arc::clone(&interrupted);
+        // The first Ctrl-C stops the program gracefully, a second one quits.
+        let handler = ctrlc::set_handler(move || {
+            if flag.swap(true, Ordering::Relaxed) {
+                exit(130);
+            }
+        });
+        match handler {
+            Ok(()) => interpreter.set_interrupt_flag(Some(Arc::clone(&interrupted))),
+            Err(err) => log::warn!("Cannot install the Ctrl-C handler: {err}"),
         }
     }
-
-    fn goto_previous_cell(&mut self) {
-        if self.cell_index == 0 {
-            self.cell_index = 29999;
-        } else {
-            self.cell_index -= 1;
+    interpreter.set_signed_cells(options.signed);
+    interpreter.set_max_steps(options.max_steps);
+    interpreter.set_timeout(options.timeout);
+    // Explicit options win over the dialect's.
+    if let Some(tape_end) = options.tape_end {
+        interpreter.set_tape_end(tape_end);
+    }
+    if let Some(arithmetic) = options.arithmetic {
+        interpreter.set_arithmetic_mode(arithmetic);
+    }
+    interpreter.set_debug_command(options.debug_command);
+    interpreter.set_extended(options.extended);
+    interpreter.set_tape_count(options.tapes);
+    if options.strict_ascii {
+        interpreter.set_strict_ascii(Some(options.allowed_comments.as_bytes()));
+    }
+    interpreter.set_opt_level(options.opt_level);
+    if let Err(err) = interpreter.set_cell_modulus(options.cell_modulus) {
+        eprintln!("{err}");
+        exit(1);
+    }
+    interpreter.set_bang_input(options.bang_input);
+    interpreter.set_count_only(options.count_only);
+    interpreter.set_crlf_output(options.crlf);
+    interpreter.set_numeric_io(options.numeric_io);
+    interpreter.set_analyze(options.analyze);
+    for &cell in &options.watchpoints {
+        interpreter.add_watchpoint(cell);
+    }
+    let output: Box<dyn Write> = match options.output_mode {
+        OutputMode::Raw => Box::new(stdout()),
+        OutputMode::Ascii => Box::new(AsciiWriter::new(stdout(), options.placeholder)),
+        OutputMode::Utf8 => Box::new(Utf8Writer::new(stdout())),
+    };
+    interpreter.set_output(Box::new(BufferedOutput::new(output, options.output_buffering)));
+    if let Some(path) = &options.emit_pcm {
+        match pcm_output(path, options.sample_rate) {
+            Ok(output) => interpreter.set_output(output),
+            Err(err) => {
+                eprintln!("Cannot create PCM file {path}: {err}");
+                exit(1);
+            }
         }
     }
-
-    fn open_loop(&mut self, current_parser_index: usize) {
-        self.loops_data.push([current_parser_index, 0]);
-        self.loops_opened += 1;
-        log!(Level::Debug, "End of the loop as of now")
+    if let Some(path) = &options.trace {
+        match File::create(path) {
+            Ok(file) => interpreter.set_trace(Some(Box::new(BufWriter::new(file)))),
+            Err(err) => {
+                eprintln!("Cannot create trace file {path}: {err}");
+                exit(1);
+            }
+        }
     }
-
-    fn close_loop(&mut self, current_parser_index: usize) {
-        if self.loops_opened == 0 {
-            eprintln!("Syntax Error: Trying to close loop, but there's no opened loop.")
-        } else {
-            let loops_cnt = self.loops_data.len();
-
-            self.loops_data[loops_cnt - 1][1] = current_parser_index;
-
-            if self.loops_opened == loops_cnt {
-                self.execute_loop_context();
+    if options.program_stdin {
+        if let Err(err) = interpreter.load_until_sentinel(Box::new(stdin().lock())) {
+            eprintln!("{err}");
+            exit(1);
+        }
+    }
+    if let Some(path) = &options.input_file {
+        match File::open(path) {
+            Ok(file) => interpreter.set_input(Box::new(BufReader::new(file))),
+            Err(err) => {
+                eprintln!("Cannot open input file {path}: {err}");
+                exit(1);
             }
-
-            self.loops_opened -= 1;
         }
+    } else if let Some(seed) = options.seed.filter(|_| !options.program_stdin) {
+        interpreter.set_input(Box::new(SeededInput::new(seed)));
     }
-
-    fn print(&self) {
-        match str::from_utf8(&[self.cells[self.cell_index]]) {
-            Ok(value) => print!("{value}"),
-            Err(err) => println!("Invalid utf8 char {err}"),
+    let filepaths = &options.filepaths;
+    let started_at = Instant::now();
+    let result = match options.mode {
+        Mode::Repl => {
+            repl(&mut interpreter);
+            Ok(())
         }
+        Mode::Debug => debug(&mut interpreter, filepaths, options.debug_window, &interrupted),
+        Mode::Run if options.mmap => interpreter.run_file_mapped(&filepaths[0]),
+        Mode::Run => run(&mut interpreter, filepaths, options.lint),
+        Mode::EmitC => emit_c(&mut interpreter, filepaths),
+        Mode::DumpOps => dump_ops(&mut interpreter, filepaths),
+        Mode::SourceMap => source_map(&mut interpreter, filepaths),
+        Mode::Version | Mode::Capabilities => unreachable!("handled above"),
+    };
+
+    if let Some(count) = options.dump {
+        stdout().flush().ok();
+        eprint!("{}", non_zero_cells(&interpreter, count));
     }
-
-    fn input(&mut self) {
-        let mut input = [0, 1];
-        stdin().read(&mut input).unwrap();
-
-        self.cells[self.cell_index] = input[0];
+    if options.profile {
+        stdout().flush().ok();
+        let profile = interpreter.profile();
+        eprintln!("{profile}");
+        if let Some(rate) = profile.steps_per_second(started_at.elapsed()) {
+            eprintln!("steps/second: {}", group_digits(rate));
+        }
+    }
+    if options.analyze {
+        let write_only = interpreter.write_only_cells();
+        if !write_only.is_empty() {
+            stdout().flush().ok();
+            let cells: Vec<String> = write_only.iter().map(|cell| cell.to_string()).collect();
+            eprintln!("warning: cells written but never printed: {}", cells.join(", "));
+        }
+    }
+    if options.profile_json {
+        stdout().flush().ok();
+        eprintln!("{}", interpreter.profile().to_json());
+    }
+    let interrupted = matches!(result, Err(InterpreterError::Interrupted { .. }));
+    if interrupted {
+        stdout().flush().ok();
+        eprintln!("\n{interpreter}");
+    }
+    // Dropping the interpreter flushes output adapters and the trace file.
+    drop(interpreter);
+    if let Err(err) = result {
+        eprintln!("{err}");
+        exit(if interrupted { 130 } else { 1 });
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::{Interpreter, InterpreterState};
-
-    #[test]
-    fn increment() {
-        let mut i = InterpreterState::new();
-        i.increment();
+fn capabilities() -> serde_json::Value {
+    json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "cell_widths": [8, 16, 32],
+        "tape_size": DEFAULT_TAPE_SIZE,
+        "eof_modes": ["zero", "minus-one", "unchanged"],
+        "tape_end_modes": ["wrap", "grow", "error", "grow-both"],
+        "arithmetic_modes": ["wrap", "saturate", "error"],
+        "opt_levels": [0, 1, 2, 3],
+        "dialects": ["classic", "urban", "pbrain"],
+        "output_modes": ["raw", "ascii", "utf8"],
+        "output_buffering": ["none", "line", "full"],
+        "dynamic_tape": true,
+        "multi_tape": true,
+    })
+}
 
-        assert_eq!(i.cells[0], 1);
+/// Output for `--emit-pcm`: a WAV file when `path` ends in `.wav`, and raw
+/// unsigned 8-bit samples otherwise.
+fn pcm_output(path: &str, sample_rate: u32) -> std::io::Result<Box<dyn Write>> {
+    let file = File::create(path)?;
+    if path.ends_with(".wav") {
+        Ok(Box::new(WavWriter::new(BufWriter::new(file), sample_rate)?))
+    } else {
+        Ok(Box::new(BufWriter::new(file)))
     }
+}
 
-    #[test]
-    fn decrement() {
-        let mut i = InterpreterState::new();
-        i.increment();
-        i.increment();
-
-        i.decrement();
-        assert_eq!(i.cells[0], 1);
+fn load(interpreter: &mut InterpreterState, filepaths: &[String]) -> Result<(), InterpreterError> {
+    for filepath in filepaths {
+        let source = match filepath.as_str() {
+            "-" => ProgramSource::Stdin,
+            path => ProgramSource::File(path.into()),
+        };
+        interpreter.load(source)?;
     }
+    Ok(())
+}
 
-    #[test]
-    fn goto_next_call_at_end() {
-        let mut i = InterpreterState::new();
-        i.cell_index = 29999;
-
-        i.goto_next_cell();
-
-        assert_eq!(i.cell_index, 0);
+fn run(
+    interpreter: &mut InterpreterState,
+    filepaths: &[String],
+    lint: bool,
+) -> Result<(), InterpreterError> {
+    load(interpreter, filepaths)?;
+    if lint {
+        interpreter.prepare()?;
+        for offset in find_infinite_loops(interpreter.instructions()) {
+            eprintln!("warning: loop at char {offset} can never end once entered");
+        }
     }
+    interpreter.parse()
+}
 
-    #[test]
-    fn goto_next_call_at_beginning() {
-        let mut i = InterpreterState::new();
-        i.goto_next_cell();
+fn emit_c(interpreter: &mut InterpreterState, filepaths: &[String]) -> Result<(), InterpreterError> {
+    load(interpreter, filepaths)?;
+    print!("{}", interpreter.transpile_c()?);
+    Ok(())
+}
 
-        assert_eq!(i.cell_index, 1);
+fn dump_ops(interpreter: &mut InterpreterState, filepaths: &[String]) -> Result<(), InterpreterError> {
+    load(interpreter, filepaths)?;
+    interpreter.prepare()?;
+    for instruction in interpreter.instructions() {
+        println!("{:>6}  {:?}", instruction.offset, instruction.op);
     }
+    Ok(())
+}
 
-    #[test]
-    fn goto_previous_cell_at_beginning() {
-        let mut i = InterpreterState::new();
-        i.goto_previous_cell();
+/// The first `count` non-zero cells, one per line with its logical index.
+fn non_zero_cells(interpreter: &InterpreterState, count: usize) -> String {
+    let origin = interpreter.origin() as isize;
+    let cells = (-origin..).zip(interpreter.tape()).filter(|&(_, &value)| value != 0);
+    cells
+        .take(count)
+        .map(|(index, _)| format!("{index:>5}: {}\n", interpreter.cell_display_value(index)))
+        .collect()
+}
 
-        assert_eq!(i.cell_index, 29999);
+fn source_map(
+    interpreter: &mut InterpreterState,
+    filepaths: &[String],
+) -> Result<(), InterpreterError> {
+    load(interpreter, filepaths)?;
+    interpreter.prepare()?;
+    println!("{}", interpreter.source_map());
+    Ok(())
+}
+
+fn debug(
+    interpreter: &mut InterpreterState,
+    filepaths: &[String],
+    window: usize,
+    interrupted: &AtomicBool,
+) -> Result<(), InterpreterError> {
+    load(interpreter, filepaths)?;
+    interpreter.prepare()?;
+    interpreter.set_history_limit(DEBUG_HISTORY_LIMIT);
+    let color = stderr().is_terminal();
+
+    let mut line = String::new();
+    loop {
+        eprint!(
+            "Press enter or n to step, s to step over a loop, u to undo a step, \
+             g <cell> to view a cell, b <char> to break, c to continue..."
+        );
+        line.clear();
+        if stdin().read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        // A Ctrl-C at the prompt only interrupts the next `c`, not a step.
+        interrupted.store(false, Ordering::Relaxed);
+
+        let command = match line.trim() {
+            "" | "n" => interpreter.step()?,
+            "s" => interpreter.step_over()?,
+            "c" => match interpreter.resume() {
+                Ok(RunStatus::Paused { offset }) => {
+                    eprintln!("Paused at char {offset}");
+                    eprintln!("{}", interpreter.render_tape(window, color));
+                    continue;
+                }
+                Ok(RunStatus::Finished) => None,
+                Err(err @ InterpreterError::Interrupted { .. }) => {
+                    eprintln!("\n{err}");
+                    eprintln!("{}", interpreter.render_tape(window, color));
+                    continue;
+                }
+                Err(err) => return Err(err),
+            },
+            "u" => {
+                if interpreter.step_back() {
+                    eprintln!("{}", interpreter.render_tape(window, color));
+                } else {
+                    eprintln!("Nothing to undo");
+                }
+                continue;
+            }
+            other if other.starts_with('g') => {
+                match other[1..].trim() {
+                    "" => interpreter.set_view_offset(None),
+                    cell => match cell.parse() {
+                        Ok(cell) => interpreter.set_view_offset(Some(cell)),
+                        Err(_) => {
+                            eprintln!("Usage: g <cell>, or g alone to follow the pointer");
+                            continue;
+                        }
+                    },
+                }
+                eprintln!("{}", interpreter.render_tape(window, color));
+                continue;
+            }
+            other if other.starts_with('b') => {
+                match other[1..].trim().parse() {
+                    Ok(offset) => interpreter.add_breakpoint(offset),
+                    Err(_) => eprintln!("Usage: b <char offset>"),
+                }
+                continue;
+            }
+            other => {
+                eprintln!("Unknown command '{other}'");
+                continue;
+            }
+        };
+        match command {
+            Some(command) => {
+                eprintln!(
+                    "'{command}' [{}] = {}",
+                    interpreter.pointer(),
+                    interpreter.cell_display_value(interpreter.pointer())
+                );
+                eprintln!("{}", interpreter.render_tape(window, color));
+                if let Some(hit) = interpreter.take_watch_hit() {
+                    eprintln!("{hit}");
+                }
+            }
+            None => {
+                eprintln!("End of program");
+                return Ok(());
+            }
+        }
     }
+}
 
-    #[test]
-    fn goto_previous_cell_at_end() {
-        let mut i = InterpreterState::new();
-        i.goto_previous_cell();
+fn repl(interpreter: &mut InterpreterState) {
+    let mut line = String::new();
+    loop {
+        print!("{}", if interpreter.is_line_pending() { "... " } else { "> " });
+        stdout().flush().ok();
+
+        line.clear();
+        match stdin().read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(err) => {
+                eprintln!("{err}");
+                exit(1);
+            }
+        }
 
-        assert_eq!(i.cell_index, 29999);
+        match interpreter.eval_line(&line) {
+            Ok(()) if !interpreter.is_line_pending() => {
+                let pointer = interpreter.pointer();
+                println!("\n[{pointer}] = {}", interpreter.cell_display_value(pointer));
+            }
+            Ok(()) => {}
+            Err(err) => eprintln!("{err}"),
+        }
     }
+    println!();
 }
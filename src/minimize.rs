@@ -0,0 +1,93 @@
+use std::io::Cursor;
+use std::mem::{self, Discriminant};
+use crate::{CapturedOutput, Interpreter, InterpreterError, InterpreterState};
+
+/// Steps each candidate may run, so reductions that loop forever are
+/// rejected instead of hanging the minimizer.
+const STEP_LIMIT: u64 = 1_000_000;
+
+const COMMANDS: &[u8] = b"+-<>.,[]";
+
+/// What a run showed: its output and, if it failed, which kind of error it
+/// failed with. Error details such as offsets move as the program shrinks,
+/// so only the kind has to match.
+#[derive(PartialEq)]
+struct Outcome {
+    output: Vec<u8>,
+    error: Option<Discriminant<InterpreterError>>,
+}
+
+fn run(program: &[u8], input: &[u8]) -> Outcome {
+    let output = CapturedOutput::default();
+    let mut interpreter = InterpreterState::new();
+    interpreter.set_max_steps(Some(STEP_LIMIT));
+    interpreter.set_input(Box::new(Cursor::new(input.to_vec())));
+    interpreter.set_output(Box::new(output.clone()));
+    let result = interpreter.run_bytes(program);
+    drop(interpreter);
+    Outcome { output: output.0.take(), error: result.err().map(|err| mem::discriminant(&err)) }
+}
+
+fn balanced(program: &[u8]) -> bool {
+    let mut depth = 0usize;
+    for &byte in program {
+        match byte {
+            b'[' => depth += 1,
+            b']' if depth == 0 => return false,
+            b']' => depth -= 1,
+            _ => {}
+        }
+    }
+    depth == 0
+}
+
+/// Shrinks `program` to a smaller one that prints the same output for
+/// `input` and, if the original fails, fails the same way. Comments go
+/// first; then ever smaller chunks and whole loops are removed while the
+/// result still behaves the same, keeping brackets balanced throughout.
+/// Programs run with default settings and a step limit, so a program that
+/// needs more steps only keeps its behaviour up to the limit.
+pub fn minimize(program: &str, input: &[u8]) -> String {
+    let mut program: Vec<u8> =
+        program.bytes().filter(|byte| COMMANDS.contains(byte)).collect();
+    let expected = run(&program, input);
+    let keeps_outcome = |candidate: &[u8]| balanced(candidate) && run(candidate, input) == expected;
+
+    loop {
+        let before = program.len();
+        let mut chunk = (program.len().next_power_of_two() / 2).max(1);
+        while chunk > 0 {
+            let mut start = 0;
+            while start < program.len() {
+                let end = (start + chunk).min(program.len());
+                let candidate = [&program[..start], &program[end..]].concat();
+                if keeps_outcome(&candidate) {
+                    program = candidate;
+                } else {
+                    // Sliding by one rather than by `chunk` finds pairs
+                    // like `><` that straddle a chunk boundary.
+                    start += 1;
+                }
+            }
+            chunk /= 2;
+        }
+
+        // Chunks rarely line up with a loop, so try dropping each one whole.
+        let mut start = 0;
+        while start < program.len() {
+            let end = (program[start] == b'[')
+                .then(|| crate::find_matching_bracket(&program, start))
+                .flatten();
+            match end {
+                Some(end) if keeps_outcome(&[&program[..start], &program[end + 1..]].concat()) => {
+                    program.drain(start..=end);
+                }
+                _ => start += 1,
+            }
+        }
+
+        if program.len() == before {
+            return String::from_utf8(program).expect("only ASCII commands are kept");
+        }
+    }
+}
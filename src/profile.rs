@@ -0,0 +1,70 @@
+use std::fmt;
+use std::time::Duration;
+use serde_json::{json, Value};
+
+/// Formats `value` with a comma between every group of three digits, so
+/// that counts in the billions stay readable.
+pub fn group_digits(value: u64) -> String {
+    let digits = value.to_string();
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+    for (index, digit) in digits.chars().enumerate() {
+        if index > 0 && (digits.len() - index).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+/// Counts of the operations executed while running a program.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Profile {
+    pub increments: u64,
+    pub decrements: u64,
+    pub moves_right: u64,
+    pub moves_left: u64,
+    pub outputs: u64,
+    pub inputs: u64,
+    pub loop_jumps: u64,
+    pub steps: u64,
+    /// Highest cell index the pointer reached.
+    pub max_cell_index: usize,
+}
+
+impl Profile {
+    /// Steps executed per second over `elapsed`, or `None` when too little
+    /// time passed to tell.
+    pub fn steps_per_second(&self, elapsed: Duration) -> Option<u64> {
+        let seconds = elapsed.as_secs_f64();
+        (seconds > 0.0).then(|| (self.steps as f64 / seconds) as u64)
+    }
+
+    /// The counters as a JSON object keyed by field name.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "increments": self.increments,
+            "decrements": self.decrements,
+            "moves_right": self.moves_right,
+            "moves_left": self.moves_left,
+            "outputs": self.outputs,
+            "inputs": self.inputs,
+            "loop_jumps": self.loop_jumps,
+            "steps": self.steps,
+            "max_cell_index": self.max_cell_index,
+        })
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "'+' executed: {}", group_digits(self.increments))?;
+        writeln!(f, "'-' executed: {}", group_digits(self.decrements))?;
+        writeln!(f, "'>' executed: {}", group_digits(self.moves_right))?;
+        writeln!(f, "'<' executed: {}", group_digits(self.moves_left))?;
+        writeln!(f, "'.' executed: {}", group_digits(self.outputs))?;
+        writeln!(f, "',' executed: {}", group_digits(self.inputs))?;
+        writeln!(f, "loop jumps:   {}", group_digits(self.loop_jumps))?;
+        writeln!(f, "highest cell: {}", group_digits(self.max_cell_index as u64))?;
+        write!(f, "total steps:  {}", group_digits(self.steps))
+    }
+}
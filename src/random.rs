@@ -0,0 +1,23 @@
+use std::io::{self, Read};
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+
+/// Input that never ends, made of pseudo-random bytes from a generator
+/// seeded with `seed`, for programs that would read `/dev/urandom`. The same
+/// seed always gives the same bytes, so such runs can be repeated.
+pub struct SeededInput {
+    rng: StdRng,
+}
+
+impl SeededInput {
+    pub fn new(seed: u64) -> Self {
+        SeededInput { rng: StdRng::seed_from_u64(seed) }
+    }
+}
+
+impl Read for SeededInput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.rng.fill_bytes(buf);
+        Ok(buf.len())
+    }
+}
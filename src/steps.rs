@@ -0,0 +1,49 @@
+use crate::{Instruction, Interpreter, InterpreterError, InterpreterState, Op};
+
+/// One executed instruction, with the pointer and current cell as they were
+/// after executing it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExecutedStep {
+    pub op: Op,
+    pub offset: usize,
+    pub pointer: isize,
+    pub cell: u32,
+}
+
+/// Iterator that executes the loaded program one instruction at a time.
+/// Returned by [`InterpreterState::steps`]. It ends after the last
+/// instruction or after yielding the first error.
+pub struct Steps<'a> {
+    interpreter: &'a mut InterpreterState,
+    failed: bool,
+}
+
+impl<'a> Steps<'a> {
+    pub(crate) fn new(interpreter: &'a mut InterpreterState) -> Self {
+        Steps { interpreter, failed: false }
+    }
+}
+
+impl Iterator for Steps<'_> {
+    type Item = Result<ExecutedStep, InterpreterError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let interpreter = &mut *self.interpreter;
+        let Instruction { op, offset, .. } =
+            interpreter.instructions.get(interpreter.instruction_pointer).copied()?;
+
+        if let Err(err) = interpreter.step() {
+            self.failed = true;
+            return Some(Err(err));
+        }
+        Some(Ok(ExecutedStep {
+            op,
+            offset,
+            pointer: interpreter.pointer(),
+            cell: interpreter.current_cell(),
+        }))
+    }
+}
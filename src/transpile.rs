@@ -0,0 +1,48 @@
+use crate::CellWidth;
+
+fn c_cell_type(cell_width: CellWidth) -> &'static str {
+    match cell_width {
+        CellWidth::Eight => "unsigned char",
+        CellWidth::Sixteen => "unsigned short",
+        CellWidth::ThirtyTwo => "unsigned int",
+    }
+}
+
+/// Emits a C program equivalent to `program`. Brackets must already be balanced.
+pub(crate) fn to_c(program: &[u8], tape_size: usize, cell_width: CellWidth) -> String {
+    let cell_type = c_cell_type(cell_width);
+    let mut source = String::new();
+    source.push_str("#include <stdio.h>\n\n");
+    source.push_str(&format!("static {cell_type} tape[{tape_size}];\n\n"));
+    source.push_str("int main(void) {\n");
+    source.push_str(&format!("    {cell_type} *p = tape;\n"));
+    source.push_str("    int c;\n");
+
+    let mut depth = 1;
+    for byte in program {
+        let statement = match byte {
+            b'+' => "++*p;",
+            b'-' => "--*p;",
+            b'>' => "++p;",
+            b'<' => "--p;",
+            b'.' => "putchar(*p);",
+            b',' => "c = getchar(); *p = c == EOF ? 0 : c;",
+            b'[' => "while (*p) {",
+            b']' => {
+                depth -= 1;
+                "}"
+            }
+            _ => continue,
+        };
+        source.push_str(&"    ".repeat(depth));
+        source.push_str(statement);
+        source.push('\n');
+        if *byte == b'[' {
+            depth += 1;
+        }
+    }
+
+    source.push_str("    return 0;\n");
+    source.push_str("}\n");
+    source
+}
@@ -0,0 +1,100 @@
+use std::io::{self, Write};
+
+/// Output adapter that collects the program's bytes and writes them to
+/// `inner` as UTF-8 text when flushed. Invalid sequences become U+FFFD, and a
+/// character still missing bytes is held back until the next flush, or
+/// replaced when the writer is dropped.
+pub struct Utf8Writer<W: Write> {
+    inner: W,
+    pending: Vec<u8>,
+}
+
+impl<W: Write> Utf8Writer<W> {
+    pub fn new(inner: W) -> Self {
+        Utf8Writer { inner, pending: Vec::new() }
+    }
+
+    /// Decodes as much of the pending bytes as possible, returning the text
+    /// and leaving an incomplete trailing character in `pending`.
+    fn decode(&mut self) -> String {
+        let mut decoded = String::new();
+        let mut rest = self.pending.as_slice();
+        loop {
+            match std::str::from_utf8(rest) {
+                Ok(valid) => {
+                    decoded.push_str(valid);
+                    rest = &[];
+                    break;
+                }
+                Err(err) => {
+                    let (valid, invalid) = rest.split_at(err.valid_up_to());
+                    decoded.push_str(&String::from_utf8_lossy(valid));
+                    let Some(len) = err.error_len() else {
+                        rest = invalid;
+                        break;
+                    };
+                    decoded.push(char::REPLACEMENT_CHARACTER);
+                    rest = &invalid[len..];
+                }
+            }
+        }
+        self.pending = rest.to_vec();
+        decoded
+    }
+}
+
+impl<W: Write> Write for Utf8Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let decoded = self.decode();
+        self.inner.write_all(decoded.as_bytes())?;
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for Utf8Writer<W> {
+    fn drop(&mut self) {
+        let mut decoded = self.decode();
+        if !self.pending.is_empty() {
+            decoded.push(char::REPLACEMENT_CHARACTER);
+        }
+        let _ = self.inner.write_all(decoded.as_bytes());
+        let _ = self.inner.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use crate::utf8::Utf8Writer;
+    use crate::CapturedOutput;
+
+    #[test]
+    fn utf8_writer_decodes_multibyte_and_replaces_invalid_bytes() {
+        let buffer = CapturedOutput::default();
+        let mut writer = Utf8Writer::new(buffer.clone());
+
+        // U+00E9 is 0xc3 0xa9, 0xff is never valid.
+        writer.write_all(b"\xc3\xa9\xff").unwrap();
+        writer.flush().unwrap();
+
+        assert_eq!(String::from_utf8(buffer.0.take()).unwrap(), "\u{e9}\u{fffd}");
+    }
+
+    #[test]
+    fn utf8_writer_replaces_incomplete_character_when_dropped() {
+        let buffer = CapturedOutput::default();
+        let mut writer = Utf8Writer::new(buffer.clone());
+
+        writer.write_all(b"a\xc3").unwrap();
+        writer.flush().unwrap();
+        assert_eq!(*buffer.0.borrow(), b"a");
+
+        drop(writer);
+        assert_eq!(String::from_utf8(buffer.0.take()).unwrap(), "a\u{fffd}");
+    }
+}
@@ -0,0 +1,17 @@
+const HIGHLIGHT: &str = "\x1b[1;30;43m";
+const RESET: &str = "\x1b[0m";
+
+/// Renders `values`, the cells starting at logical index `start`, as one row.
+/// The cell at `pointer` is highlighted with ANSI colors, or wrapped in
+/// brackets when `color` is false.
+pub(crate) fn render(values: &[i64], start: isize, pointer: isize, color: bool) -> String {
+    let cells: Vec<String> = (start..)
+        .zip(values)
+        .map(|(index, value)| match (index == pointer, color) {
+            (true, true) => format!("{HIGHLIGHT} {value} {RESET}"),
+            (true, false) => format!("[{value}]"),
+            (false, _) => format!(" {value} "),
+        })
+        .collect();
+    format!("{start:>5}: {}", cells.join(""))
+}
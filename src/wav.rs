@@ -0,0 +1,87 @@
+use std::io::{self, Seek, SeekFrom, Write};
+
+/// Size of the RIFF header in front of the samples.
+const HEADER_LEN: u32 = 44;
+
+/// Output adapter that stores the program's bytes as a mono WAV file of
+/// unsigned 8-bit samples. The header's lengths are updated on every flush
+/// and when the writer is dropped, so the file is valid whenever it has just
+/// been flushed.
+pub struct WavWriter<W: Write + Seek> {
+    inner: W,
+    sample_rate: u32,
+    samples: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut inner: W, sample_rate: u32) -> io::Result<Self> {
+        inner.write_all(&header(sample_rate, 0))?;
+        Ok(WavWriter { inner, sample_rate, samples: 0 })
+    }
+}
+
+fn header(sample_rate: u32, samples: u32) -> Vec<u8> {
+    let mut header = Vec::with_capacity(HEADER_LEN as usize);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&(HEADER_LEN - 8 + samples).to_le_bytes());
+    header.extend_from_slice(b"WAVEfmt ");
+    header.extend_from_slice(&16u32.to_le_bytes());
+    // PCM, one channel.
+    header.extend_from_slice(&1u16.to_le_bytes());
+    header.extend_from_slice(&1u16.to_le_bytes());
+    header.extend_from_slice(&sample_rate.to_le_bytes());
+    // One byte per sample: the byte rate is the sample rate.
+    header.extend_from_slice(&sample_rate.to_le_bytes());
+    header.extend_from_slice(&1u16.to_le_bytes());
+    header.extend_from_slice(&8u16.to_le_bytes());
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&samples.to_le_bytes());
+    header
+}
+
+impl<W: Write + Seek> Write for WavWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.samples = self.samples.saturating_add(written as u32);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.seek(SeekFrom::Start(0))?;
+        self.inner.write_all(&header(self.sample_rate, self.samples))?;
+        self.inner.seek(SeekFrom::End(0))?;
+        self.inner.flush()
+    }
+}
+
+impl<W: Write + Seek> Drop for WavWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+    use crate::wav::WavWriter;
+
+    #[test]
+    fn wav_writer_stores_output_as_samples() {
+        let mut file = Cursor::new(Vec::new());
+        {
+            let mut wav = WavWriter::new(&mut file, 8000).unwrap();
+            wav.write_all(&[0, 128]).unwrap();
+            wav.flush().unwrap();
+            wav.write_all(&[255]).unwrap();
+        }
+        let bytes = file.into_inner();
+
+        assert_eq!(&bytes[..4], b"RIFF");
+        assert_eq!(&bytes[4..8], &39u32.to_le_bytes());
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(&bytes[24..28], &8000u32.to_le_bytes());
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(&bytes[40..44], &3u32.to_le_bytes());
+        assert_eq!(&bytes[44..], &[0, 128, 255]);
+    }
+}
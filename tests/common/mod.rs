@@ -0,0 +1,19 @@
+//! Helpers shared by the integration tests.
+
+use std::cell::RefCell;
+use std::io::Write;
+use std::rc::Rc;
+
+/// Output sink that stays readable after being handed to the interpreter.
+#[derive(Clone, Default)]
+pub struct SharedBuffer(pub Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
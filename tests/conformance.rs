@@ -0,0 +1,66 @@
+//! Known programs with known outputs, run through the whole library.
+
+use std::io::Cursor;
+use beefin::{EofBehavior, Interpreter, InterpreterState, OptLevel};
+use common::SharedBuffer;
+
+mod common;
+
+/// Echoes its input until EOF, which must read as 0.
+const CAT: &str = ",[.,]";
+
+/// ROT13 from the Brainfuck article on Wikipedia, without its comments. It
+/// expects EOF to leave the cell unchanged.
+const ROT13: &str = "-,+[-[>>++++[>++++++++<-]<+<-[>+>+>-[>>>]<[[>+<-]>>+>]<<<<<-]]>>>[-]+>--[-[<->+++[-]]]<[++++++++++++<[>-[>+>>]>[+[<+>-]>+>>]<<<<<-]>>[<+>-]>[-[-<<[-]>>]<<[<<->>-]>>]<<[<<+>>-]]<[-]<.[-]<-,+]";
+
+/// Prints the first ten Fibonacci numbers as raw bytes.
+const FIBONACCI: &str = "++++++++++>>+<<[>.[->>+<<]>[-<+>>+<]>[-<+>]<<<-]";
+
+/// Daniel Cristofani's program printing the squares from 0 to 10000.
+const SQUARES: &str = "++++[>+++++<-]>[<+++++>-]+<+[>[>+>+<<-]++>>[<<+>>-]>>>[-]++>[-]+>>>+[[-]++++++>>>]<<<[[<++++++++<++>>-]+<.<[>----<-]<]<<[>>>>>[>>>[-]+++++++++<[>-<-]+++++++++>[-[<->-]+[<<<]]<[>+<-]>]<<-]<<-]";
+
+/// Runs `program` at every optimization level, checking that they agree, and
+/// returns its output.
+fn run(program: &str, input: &[u8], eof: EofBehavior) -> Vec<u8> {
+    let levels = [OptLevel::None, OptLevel::Basic, OptLevel::Full, OptLevel::Aggressive];
+    let outputs: Vec<Vec<u8>> = levels
+        .into_iter()
+        .map(|opt_level| {
+            let output = SharedBuffer::default();
+            let mut interpreter = InterpreterState::new();
+            interpreter.set_opt_level(opt_level);
+            interpreter.set_eof_behavior(eof);
+            interpreter.set_max_steps(Some(10_000_000));
+            interpreter.set_input(Box::new(Cursor::new(input.to_vec())));
+            interpreter.set_output(Box::new(output.clone()));
+            interpreter.run_string(program).unwrap();
+            let bytes = output.0.borrow().clone();
+            bytes
+        })
+        .collect();
+    assert!(outputs.iter().all(|output| *output == outputs[0]));
+    outputs[0].clone()
+}
+
+#[test]
+fn cat_echoes_input_until_eof() {
+    let input = b"hello\nworld\n";
+    assert_eq!(run(CAT, input, EofBehavior::Zero), input);
+}
+
+#[test]
+fn rot13_rotates_letters_only() {
+    assert_eq!(run(ROT13, b"Hello, World!", EofBehavior::Unchanged), b"Uryyb, Jbeyq!");
+    assert_eq!(run(ROT13, b"Uryyb, Jbeyq!", EofBehavior::Unchanged), b"Hello, World!");
+}
+
+#[test]
+fn fibonacci_prints_first_ten_numbers() {
+    assert_eq!(run(FIBONACCI, b"", EofBehavior::Zero), [0, 1, 1, 2, 3, 5, 8, 13, 21, 34]);
+}
+
+#[test]
+fn squares_prints_squares_up_to_10000() {
+    let expected: String = (0..=100).map(|n| format!("{}\n", n * n)).collect();
+    assert_eq!(String::from_utf8(run(SQUARES, b"", EofBehavior::Zero)).unwrap(), expected);
+}
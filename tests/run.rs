@@ -0,0 +1,393 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+use beefin::{Interpreter, InterpreterState};
+use common::SharedBuffer;
+
+mod common;
+
+const PRINT_A: &str = "+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++.";
+
+/// The canonical "Hello World!" program from the Brainfuck article on Wikipedia.
+const HELLO_WORLD: &str = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+
+#[test]
+fn runs_program_from_string() {
+    let mut interpreter = InterpreterState::new();
+    interpreter.run_string(PRINT_A).unwrap();
+}
+
+#[test]
+fn runs_hello_world_end_to_end() {
+    let output = SharedBuffer::default();
+    let mut interpreter = InterpreterState::new();
+    interpreter.set_output(Box::new(output.clone()));
+
+    interpreter.run_string(HELLO_WORLD).unwrap();
+
+    assert_eq!(*output.0.borrow(), b"Hello World!\n");
+}
+
+#[test]
+fn binary_runs_program_file() {
+    let path = std::env::temp_dir().join("beefin_binary_runs_program_file.bf");
+    std::fs::write(&path, PRINT_A).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_beefin"))
+        .arg(&path)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"A");
+}
+
+fn run_binary_with_stdin(args: &[&str], stdin: &[u8]) -> std::process::Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_beefin"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // The binary may exit before reading everything, e.g. on a bad flag.
+    child.stdin.take().unwrap().write_all(stdin).ok();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn binary_reads_program_from_stdin_with_dash() {
+    let output = run_binary_with_stdin(&["-"], PRINT_A.as_bytes());
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"A");
+}
+
+#[test]
+fn binary_appends_program_from_stdin_after_files() {
+    let path = std::env::temp_dir().join("beefin_binary_appends_program_from_stdin.bf");
+    std::fs::write(&path, &PRINT_A[..64]).unwrap();
+
+    let output = run_binary_with_stdin(&[path.to_str().unwrap(), "-"], b"+\n.\n");
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"A");
+}
+
+#[test]
+fn binary_reads_program_from_piped_stdin_without_arguments() {
+    let output = run_binary_with_stdin(&[], PRINT_A.as_bytes());
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"A");
+}
+
+#[test]
+fn binary_prints_version() {
+    let output = Command::new(env!("CARGO_BIN_EXE_beefin")).arg("--version").output().unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!("beefin {}\n", env!("CARGO_PKG_VERSION"))
+    );
+}
+
+#[test]
+fn binary_prints_capabilities_as_json() {
+    let output = Command::new(env!("CARGO_BIN_EXE_beefin"))
+        .arg("--capabilities")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let capabilities: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(capabilities["tape_size"], 30000);
+    assert_eq!(capabilities["cell_widths"], serde_json::json!([8, 16, 32]));
+    assert_eq!(capabilities["dynamic_tape"], true);
+}
+
+#[test]
+fn binary_runs_multiple_program_files_on_one_tape() {
+    let dir = std::env::temp_dir();
+    let first = dir.join("beefin_binary_multiple_first.bf");
+    let second = dir.join("beefin_binary_multiple_second.bf");
+    std::fs::write(&first, "++++++++[>++++++++<-]>+").unwrap();
+    std::fs::write(&second, ".+.").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_beefin"))
+        .arg(&first)
+        .arg(&second)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"AB");
+}
+
+fn run_past_right_end(tape_end: &str) -> std::process::Output {
+    let program = format!("{}{PRINT_A}{}.", ">".repeat(30000), "<".repeat(30000));
+    run_binary_with_stdin(&[&format!("--tape-end={tape_end}"), "-"], program.as_bytes())
+}
+
+#[test]
+fn binary_tape_end_wrap_returns_to_first_cell() {
+    let output = run_past_right_end("wrap");
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"AA");
+}
+
+#[test]
+fn binary_tape_end_grow_appends_cells() {
+    let output = run_past_right_end("grow");
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"A\0");
+}
+
+#[test]
+fn binary_tape_end_error_stops_execution() {
+    let output = run_past_right_end("error");
+
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn binary_rejects_unknown_tape_end() {
+    let output = run_past_right_end("bounce");
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("wrap, grow, error"));
+}
+
+#[test]
+fn binary_writes_trace_file() {
+    let dir = std::env::temp_dir();
+    let program = dir.join("beefin_binary_trace.bf");
+    let trace = dir.join("beefin_binary_trace.log");
+    std::fs::write(&program, "+>+<-").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_beefin"))
+        .arg("--trace")
+        .arg(&trace)
+        .arg(&program)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let trace = std::fs::read_to_string(&trace).unwrap();
+    assert_eq!(trace.lines().count(), 5);
+    assert_eq!(trace.lines().last(), Some("offset=4 op='-' ptr=0 cell=0"));
+}
+
+#[test]
+fn binary_program_stdin_leaves_rest_for_input() {
+    let output = run_binary_with_stdin(&["--program-stdin"], b",[.,]\n%%\nhi");
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"hi");
+}
+
+#[test]
+fn binary_prints_profile_as_json() {
+    let output = run_binary_with_stdin(&["--profile-json", "-"], b"++[>+<-]>.");
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"\x02");
+    let profile: serde_json::Value = serde_json::from_slice(&output.stderr).unwrap();
+    for key in ["increments", "decrements", "moves_right", "moves_left", "outputs", "steps"] {
+        assert!(profile[key].is_u64(), "missing {key}");
+    }
+    assert_eq!(profile["steps"], 15);
+    assert_eq!(profile["max_cell_index"], 1);
+}
+
+#[test]
+fn binary_count_only_reports_without_output() {
+    let dir = std::env::temp_dir();
+    let program = dir.join("beefin_binary_count_only.bf");
+    std::fs::write(&program, ",+.>++.").unwrap();
+
+    let output = run_binary_with_stdin(&["--count-only", program.to_str().unwrap()], b"zz");
+
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("'.' executed: 2"), "{stderr}");
+    assert!(stderr.contains("    0: 1\n    1: 2\n"), "{stderr}");
+}
+
+#[test]
+fn binary_emits_pcm_samples() {
+    let dir = std::env::temp_dir();
+    let program = dir.join("beefin_binary_emit_pcm.bf");
+    std::fs::write(&program, "+.+.-.").unwrap();
+    let wav = dir.join("beefin_binary_emit_pcm.wav");
+    let raw = dir.join("beefin_binary_emit_pcm.pcm");
+
+    for (path, rate) in [(&wav, "22050"), (&raw, "8000")] {
+        let args = [
+            "--emit-pcm",
+            path.to_str().unwrap(),
+            "--sample-rate",
+            rate,
+            program.to_str().unwrap(),
+        ];
+        let output = run_binary_with_stdin(&args, b"");
+        assert!(output.status.success());
+        assert!(output.stdout.is_empty());
+    }
+
+    let wav = std::fs::read(wav).unwrap();
+    assert_eq!(&wav[..4], b"RIFF");
+    assert_eq!(&wav[24..28], &22050u32.to_le_bytes());
+    assert_eq!(&wav[44..], &[1, 2, 1]);
+    assert_eq!(std::fs::read(raw).unwrap(), [1, 2, 1]);
+}
+
+#[test]
+fn binary_reads_input_from_file() {
+    let dir = std::env::temp_dir();
+    let program = dir.join("beefin_binary_input_file.bf");
+    std::fs::write(&program, ",>,>,>,").unwrap();
+    let input = dir.join("beefin_binary_input_file.txt");
+    std::fs::write(&input, "ABC").unwrap();
+
+    let output = run_binary_with_stdin(
+        &["--dump", "--input-file", input.to_str().unwrap(), program.to_str().unwrap()],
+        b"ignored",
+    );
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("    0: 65\n    1: 66\n    2: 67\n"), "{stderr}");
+}
+
+#[test]
+fn binary_dumps_the_first_non_zero_cells() {
+    let output = run_binary_with_stdin(&["--dump=2", "-"], b"+>>++>+++");
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "    0: 1\n    2: 2\n");
+}
+
+#[test]
+fn binary_dumps_compiled_ops() {
+    let output = run_binary_with_stdin(&["--dump-ops", "-"], b"+++[-]");
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().map(str::trim).collect();
+    assert_eq!(lines, ["0  Add(3)", "3  SetZero(-1)"]);
+}
+
+fn run_with_log_flag(flag: &str) -> std::process::Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_beefin"))
+        .args([flag, "-"])
+        .env("RUST_LOG", "debug")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"comment +").unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn binary_quiet_hides_debug_logs() {
+    let output = run_with_log_flag("--quiet");
+
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty(), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn binary_verbose_shows_debug_logs() {
+    let output = run_with_log_flag("--verbose");
+
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Skipped 8 comment bytes"));
+}
+
+#[test]
+fn binary_rejects_unknown_dialect() {
+    let output = run_binary_with_stdin(&["--dialect=brainfork", "-"], PRINT_A.as_bytes());
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("expected one of: classic, urban, pbrain"), "{stderr}");
+}
+
+#[test]
+fn binary_dialect_sets_eof_behavior() {
+    let output = run_binary_with_stdin(&["--dialect=urban", "--program-stdin"], b"+,.\n%%\n");
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, [1]);
+}
+
+#[test]
+fn binary_pbrain_defines_with_colons_and_calls_with_question_marks() {
+    // Procedure 0 adds 13 to cell 1, and five calls make it 'A'.
+    let program = b":>+++++++++++++<:?????>.";
+    let output = run_binary_with_stdin(&["--dialect=pbrain", "-"], program);
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"A");
+}
+
+/// Prints the byte 200, which is neither ASCII nor valid UTF-8 on its own.
+const PRINT_200: &str = "++++++++++[>++++++++++++++++++++<-]>.";
+
+#[test]
+fn binary_output_modes_print_byte_200() {
+    let cases: [(&str, &[u8]); 3] = [
+        ("--output-mode=raw", &[200]),
+        ("--output-mode=ascii", b"?"),
+        ("--output-mode=utf8", "\u{fffd}".as_bytes()),
+    ];
+    for (flag, expected) in cases {
+        let output = run_binary_with_stdin(&[flag, "-"], PRINT_200.as_bytes());
+        assert!(output.status.success(), "{flag}");
+        assert_eq!(output.stdout, expected, "{flag}");
+    }
+
+    let args = ["--output-mode=ascii", "--placeholder=*", "-"];
+    let output = run_binary_with_stdin(&args, PRINT_200.as_bytes());
+    assert_eq!(output.stdout, b"*");
+}
+
+#[test]
+fn binary_output_buffering_modes_print_the_same() {
+    for flag in ["--output-buffering=none", "--output-buffering=line", "--output-buffering=full"] {
+        let output = run_binary_with_stdin(&[flag, "-"], PRINT_A.as_bytes());
+        assert!(output.status.success(), "{flag}");
+        assert_eq!(output.stdout, b"A", "{flag}");
+    }
+
+    let output = run_binary_with_stdin(&["--output-buffering=some", "-"], PRINT_A.as_bytes());
+    assert!(!output.status.success());
+}
+
+#[test]
+fn binary_strict_ascii_reports_the_disallowed_byte() {
+    let program = "+++ adds three; .";
+    let args = ["--strict-ascii", "--allow-comments=ad", "-"];
+    let output = run_binary_with_stdin(&args, program.as_bytes());
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("byte 0x73 at char 7"), "{stderr}");
+}
+
+#[test]
+fn binary_seed_gives_repeatable_input() {
+    const PRINT_INPUT: &str = ",.,.,.,.,.,.,.,.";
+    let run = |seed| run_binary_with_stdin(&["--seed", seed, "-"], PRINT_INPUT.as_bytes()).stdout;
+
+    assert_eq!(run("7").len(), 8);
+    assert_eq!(run("7"), run("7"));
+    assert_ne!(run("7"), run("8"));
+}